@@ -9,11 +9,14 @@ use piston::event_loop::{Events, EventSettings};
 use piston::input::{RenderArgs, RenderEvent, UpdateArgs, UpdateEvent};
 
 use crate::boids::{Boid, BoidVec};
-use crate::drawable::Drawable;
-use crate::units::Unit;
+use crate::command::CommandOrder;
+use crate::drawable::{draw_ghost, Drawable};
+use crate::units::{Goal, Unit};
 use crate::ops::Vec2f;
-use crate::player::PlayerState;
-use crate::world::World;
+use crate::player::{PlayerAction, PlayerState};
+use crate::traits::Controllable;
+use crate::troops::TroopCatalog;
+use crate::world::{World, WorldId, CLICK_PRECISION, TICK};
 
 pub struct App {
     pub(crate) gl: GlGraphics, // OpenGL drawing backend.
@@ -21,19 +24,29 @@ pub struct App {
     player: PlayerState,
     mouse_pos: Vec2f,
     world: World,
+    ///time not yet simulated, always less than a tick after update
+    accumulator: f64,
 }
 
+///Drops time instead of spiralling when a frame takes too long
+const MAX_TICKS_PER_UPDATE: usize = 8;
+
 const BOID_SIZE: f64 = 24.;
 const CURSOR_SIZE: f64 = 12.;
-pub const CLICK_PRECISION: f64 = 12.;
 
 impl App {
-    pub fn new(gl: OpenGL) -> Self {
+    pub fn new(gl: OpenGL, seed: u64) -> Self {
+        let mut world = World::single_company(seed);
+        if let Err(e) = TroopCatalog::startup().and_then(|c| world.set_catalog(c)) {
+            eprintln!("troop catalog: {}, keeping the built in one", e);
+        }
+
         App {
             gl: GlGraphics::new(gl),
             player: Default::default(),
             mouse_pos: Default::default(),
-            world: World::single_company(),
+            world,
+            accumulator: 0.,
         }
     }
 
@@ -54,18 +67,39 @@ impl App {
 
 
         let c = self.gl.draw_begin(args.viewport());
+        let alpha = self.accumulator / TICK;
+
+        self.world.view = Some(p.pos + Vec2f {
+            x: args.window_size[0] / 2.,
+            y: args.window_size[1] / 2.,
+        });
 
         // Clear the screen.
         clear(GREEN, &mut self.gl);
 
         for group in &mut self.world.groups {
             match group {
-                Unit::BasicUnit(company) => {company.draw(c, &mut self.gl)}
-                Unit::CompositeUnit(b) => {}
+                Unit::BasicUnit(company) => {company.draw(c, &mut self.gl, alpha)}
+                Unit::CompositeUnit(b) => {
+                    for company in b.troops.iter() {
+                        company.draw(c, &mut self.gl, alpha);
+                    }
+                }
             }
             //group.draw(c,&mut self.gl)
         }
 
+        //preview of the front being dragged out, the same slots the order will produce
+        if p.r_pressed && !p.column_pressed && !p.alt_pressed && (p.r2 - p.r1).man() >= CLICK_PRECISION {
+            let mut rng = self.world.rng.clone();
+
+            for (id, goal) in self.world.front_goals(p.r1, p.r2, &p.selected, p.command) {
+                if let (Some(company), Goal::Front(p1, p2, dir)) = (self.world.find_company(id), goal) {
+                    draw_ghost(&company.front_slots(p1, p2, dir, &mut rng), c, &mut self.gl);
+                }
+            }
+        }
+
         let transform = c
             .transform
             .trans(p.l1.x, p.l1.y)
@@ -123,7 +157,46 @@ impl App {
             Input::Button(a) => match a.button {
                 Button::Keyboard(k) => match k {
                     Key::A => {}
-                    Key::D => {}
+                    //selected companies form a battalion, which takes over the selection
+                    Key::B => if let ButtonState::Press = a.state {
+                        let ids: Vec<WorldId> = p.selected.iter().copied().collect();
+                        if let Some(id) = self.world.assemble(&ids) {
+                            p.selected.clear();
+                            p.selected.insert(id);
+                        }
+                    },
+                    //widen the selection to the next echelon of the chain of command
+                    Key::G => if let ButtonState::Press = a.state {
+                        let ids: Vec<WorldId> = p.selected.iter().copied().collect();
+                        if let Some(node) = self.world.command.widen(&ids) {
+                            p.selected = self.world.command.companies_under(node).into_iter().collect();
+                            p.command = Some(node);
+                        }
+                    },
+                    //selected companies leave their battalions
+                    Key::U => if let ButtonState::Press = a.state {
+                        for id in p.selected.iter() {
+                            self.world.detach(*id);
+                        }
+                    },
+                    Key::C => match a.state {
+                        ButtonState::Press => p.column_pressed = true,
+                        ButtonState::Release => p.column_pressed = false,
+                    },
+                    //selected battalions run their first known drill
+                    Key::D => if let ButtonState::Press = a.state {
+                        for group in self.world.groups.iter_mut() {
+                            if let Unit::CompositeUnit(battalion) = group {
+                                if p.selected.contains(&battalion.id) {
+                                    battalion.start_drill(0);
+                                }
+                            }
+                        }
+                    },
+                    Key::P => match a.state {
+                        ButtonState::Press => p.patrol_pressed = true,
+                        ButtonState::Release => p.patrol_pressed = false,
+                    },
                     Key::S => {}
                     Key::W => {}
                     Key::Right => {}
@@ -134,8 +207,14 @@ impl App {
                         ButtonState::Press => p.ctrl_pressed = true,
                         ButtonState::Release => p.ctrl_pressed = false,
                     },
-                    Key::LShift => {}
-                    Key::LAlt => {}
+                    Key::LShift => match a.state {
+                        ButtonState::Press => p.shift_pressed = true,
+                        ButtonState::Release => p.shift_pressed = false,
+                    },
+                    Key::LAlt => match a.state {
+                        ButtonState::Press => p.alt_pressed = true,
+                        ButtonState::Release => p.alt_pressed = false,
+                    },
                     Key::LGui => {}
                     Key::RCtrl => {}
                     Key::RShift => {}
@@ -146,14 +225,20 @@ impl App {
                 Button::Mouse(mb) => match mb {
                     MouseButton::Unknown => {}
                     MouseButton::Left => match a.state {
+                        //grabbing a waypoint marker edits the goal instead of selecting
                         ButtonState::Press => {
-                            p.l_pressed = true;
-                            p.l1 = self.mouse_pos;
-                            p.l2 = self.mouse_pos;
+                            p.dragged_goal = self.world.goal_at(self.mouse_pos);
+                            if p.dragged_goal.is_none() {
+                                p.l_pressed = true;
+                                p.l1 = self.mouse_pos;
+                                p.l2 = self.mouse_pos;
+                            }
                         }
                         ButtonState::Release => {
-                            p.l_pressed = false;
-                            p.l_click = true;
+                            if p.dragged_goal.take().is_none() {
+                                p.l_pressed = false;
+                                p.l_click = true;
+                            }
                         }
                     },
                     MouseButton::Right => match a.state {
@@ -164,7 +249,20 @@ impl App {
                         }
                         ButtonState::Release => {
                             p.r_pressed = false;
-                            p.r_click = true;
+                            //right clicking a waypoint marker deletes its goal
+                            let marker = if (p.r2 - p.r1).man() < CLICK_PRECISION {
+                                self.world.goal_at(p.r2)
+                            } else {
+                                None
+                            };
+                            match marker {
+                                Some((id, i, _)) => {
+                                    if let Some(company) = self.world.find_company_mut(id) {
+                                        company.remove_goal(i);
+                                    }
+                                }
+                                None => p.r_click = true,
+                            }
                         }
                     },
                     MouseButton::Middle => {}
@@ -190,6 +288,17 @@ impl App {
                     if p.r_pressed {
                         p.r2 = self.mouse_pos;
                     }
+                    if let Some((id, i, at)) = p.dragged_goal {
+                        //a goal finished or inserted shifts the queue, the held index then
+                        //points elsewhere and the marker is let go until the button is released
+                        let held = self.world.find_company_mut(id).filter(|c| {
+                            c.goals.get(i).and_then(Goal::target).map_or(false, |t| (t - at).len() < 1e-6)
+                        });
+                        if let Some(company) = held {
+                            company.move_goal(i, self.mouse_pos);
+                            p.dragged_goal = Some((id, i, self.mouse_pos));
+                        }
+                    }
                 }
                 Motion::MouseRelative(_) => {}
                 Motion::MouseScroll(_) => {}
@@ -208,17 +317,59 @@ impl App {
     }
 
     pub fn update(&mut self, args: &UpdateArgs) {
-        self.world.process_interactions();
+        //a selected command node gives the order, its companies do not take it one by one
+        let mut commanded = vec![];
+        if let Some(node) = self.player.command {
+            commanded = self.world.command.companies_under(node);
+            if !matches!(self.player.action, PlayerAction::None) {
+                let center = self.world.command.member(node, &self.world).center;
+                let dir = commanded.first()
+                    .and_then(|id| self.world.find_company(*id))
+                    .map_or(Vec2f { x: 1., y: 0. }, |c| c.direction);
+
+                CommandOrder { world: &mut self.world, node }.assign_goals(self.player.action, dir, center);
+            }
+        }
 
         for group in &mut self.world.groups {
-            if self.player.selected.contains(&group.id) {
-                group.selected = true;
-                group.assign_goals(self.player.action);
-            } else {
-                group.selected = false;
+            //a selected battalion shares the order out among its companies
+            if let Unit::CompositeUnit(battalion) = group {
+                battalion.selected = self.player.selected.contains(&battalion.id);
+                if battalion.selected {
+                    battalion.assign_goals(self.player.action, battalion.direction, battalion.center);
+                    for company in battalion.troops.iter_mut() {
+                        company.selected = true;
+                    }
+                    continue;
+                }
             }
 
-            group.process_boids(args.dt);
+            for company in group.companies_mut() {
+                if commanded.contains(&company.id) {
+                    company.selected = true;
+                } else if self.player.selected.contains(&company.id) {
+                    company.selected = true;
+                    if !matches!(self.player.action, PlayerAction::None) {
+                        company.set_patrol(self.player.patrol);
+                    }
+                    company.assign_goals(self.player.action, company.direction, company.center);
+                } else {
+                    company.selected = false;
+                }
+            }
+        }
+
+        self.accumulator += args.dt;
+
+        let mut ticks = 0;
+        while self.accumulator >= TICK {
+            if ticks == MAX_TICKS_PER_UPDATE {
+                self.accumulator = 0.;
+                break;
+            }
+            self.world.step(TICK);
+            self.accumulator -= TICK;
+            ticks += 1;
         }
     }
 }
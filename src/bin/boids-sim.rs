@@ -0,0 +1,113 @@
+use boids::units::{BasicUnit, Unit};
+use boids::troops::{TroopCatalog, CATALOG_PATH};
+use boids::world::{World, TICK};
+use std::env;
+use std::process;
+
+const DEFAULT_TICKS: usize = 600;
+
+const USAGE: &str = "usage: boids-sim [scenario.ron] [--ticks N] [--dt SECONDS] [--seed N] [--troops catalog.ron]";
+
+///Runs a scenario without a window and prints per-unit stats
+fn main() {
+    let mut scenario: Option<String> = None;
+    let mut ticks = DEFAULT_TICKS;
+    let mut dt = TICK;
+    let mut seed: Option<u64> = None;
+    let mut troops: Option<String> = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ticks" => ticks = parse_next(&mut args),
+            "--dt" => dt = parse_next(&mut args),
+            "--seed" => seed = Some(parse_next(&mut args)),
+            "--troops" => troops = Some(parse_next(&mut args)),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            path => scenario = Some(path.to_string()),
+        }
+    }
+
+    //the catalog comes first, the scenario's companies are equipped from it
+    let catalog = match &troops {
+        Some(path) => TroopCatalog::load(path),
+        None => TroopCatalog::startup(),
+    };
+    let catalog = catalog.unwrap_or_else(|e| {
+        eprintln!("failed to load troops from {}: {}", troops.as_deref().unwrap_or(CATALOG_PATH), e);
+        process::exit(1)
+    });
+
+    let mut world = match &scenario {
+        Some(path) => World::load(path, catalog).unwrap_or_else(|e| {
+            eprintln!("failed to load {}: {}", path, e);
+            process::exit(1)
+        }),
+        None => {
+            let mut world = World::single_company(seed.unwrap_or_else(rand::random));
+            if let Err(e) = world.set_catalog(catalog) {
+                eprintln!("failed to equip troops: {}", e);
+                process::exit(1)
+            }
+            world
+        }
+    };
+
+    //command line seed overrides the scenario's
+    if let Some(seed) = seed {
+        world.set_seed(seed);
+    }
+
+    for _ in 0..ticks {
+        world.step(dt);
+    }
+
+    println!("seed {}, {} ticks, {:.2} s simulated", world.seed, ticks, ticks as f64 * dt);
+
+    for group in world.groups.iter() {
+        match group {
+            Unit::BasicUnit(company) => print_company(company, ""),
+            Unit::CompositeUnit(battalion) => {
+                println!(
+                    "battalion {} at ({:.1}, {:.1}), {} companies, drill {:?}",
+                    battalion.id,
+                    battalion.center.x,
+                    battalion.center.y,
+                    battalion.troops.len(),
+                    battalion.drill.as_ref().map(|d| d.status)
+                );
+                for company in battalion.troops.iter() {
+                    print_company(company, "  ");
+                }
+            }
+        }
+    }
+}
+
+fn parse_next<T: std::str::FromStr>(args: &mut impl Iterator<Item = String>) -> T {
+    match args.next().and_then(|a| a.parse().ok()) {
+        Some(v) => v,
+        None => {
+            eprintln!("{}", USAGE);
+            process::exit(2)
+        }
+    }
+}
+
+fn print_company(company: &BasicUnit, indent: &str) {
+    println!(
+        "{}company {} ({}) at ({:.1}, {:.1}), {} boids, goal {:?}, fatigue {:.2}, morale {:.2}",
+        indent,
+        company.id,
+        if company.troop_type.is_empty() { "infantry" } else { &company.troop_type },
+        company.center.x,
+        company.center.y,
+        company.num_boids(),
+        company.goals.front(),
+        company.fatigue,
+        company.morale
+    );
+}
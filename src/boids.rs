@@ -5,7 +5,7 @@ use rand::Rng;
 use serde::{Deserialize, Serialize};
 use soa_derive::{SoAIndex, StructOfArray};
 
-#[derive(Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub enum BoidState {
     Stationary,
     Accelerating,
@@ -20,32 +20,116 @@ impl Default for BoidState {
     }
 }
 
-#[derive(Copy, Clone, StructOfArray, Default, Serialize, Deserialize)]
+///Speeds closer than this count as equal
+const SPEED_EPS: f64 = 1.;
+
+impl BoidState {
+    ///State after a kinematic step, from the speed reached and the speed steered for
+    pub fn classify(speed: f64, target_speed: f64, idle: bool) -> BoidState {
+        if speed < SPEED_EPS && target_speed < SPEED_EPS {
+            if idle {
+                BoidState::Idle
+            } else {
+                BoidState::Stationary
+            }
+        } else if target_speed > speed + SPEED_EPS {
+            BoidState::Accelerating
+        } else if target_speed < speed - SPEED_EPS {
+            BoidState::Decelerating
+        } else {
+            BoidState::Marching
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Condition {
+    Fit,
+    ///slowed down, out of the fight once health runs out
+    Wounded,
+    Dead,
+}
+
+impl Default for Condition {
+    fn default() -> Self {
+        Condition::Fit
+    }
+}
+
+///Soldiers below this much health are wounded
+const WOUNDED_HEALTH: f32 = 0.5;
+
+impl Condition {
+    pub fn from_health(health: f32) -> Condition {
+        if health <= 0. {
+            Condition::Dead
+        } else if health < WOUNDED_HEALTH {
+            Condition::Wounded
+        } else {
+            Condition::Fit
+        }
+    }
+}
+
+#[derive(Copy, Clone, StructOfArray, Serialize, Deserialize)]
 #[soa_derive(Serialize, Deserialize)]
 pub struct Boid {
     pub pos: Vec2f,
+    ///pos at the previous tick, for render interpolation
+    pub prev_pos: Vec2f,
     pub vel: Vec2f,
     pub(crate) r: f64,
     pub state: BoidState,
     pub color: [f32; 4], //todo: so far no reason to store color
+
+    ///1 unhurt, 0 dead
+    pub health: f32,
+    ///1 fresh, 0 spent
+    pub stamina: f32,
+    ///missiles left
+    pub ammo: u8,
+    pub condition: Condition,
+    ///quality of this soldier's equipment, 1 is standard issue
+    pub kit: f32,
+}
+
+impl Default for Boid {
+    fn default() -> Self {
+        Boid {
+            pos: Vec2f::default(),
+            prev_pos: Vec2f::default(),
+            vel: Vec2f::default(),
+            r: 0.,
+            state: BoidState::default(),
+            color: [0.; 4],
+            health: 1.,
+            stamina: 1.,
+            ammo: 0,
+            condition: Condition::Fit,
+            kit: 1.,
+        }
+    }
 }
 
 const SPREAD: f64 = 600.;
 const VEL_SPREAD: f64 = 500.;
+///kit is drawn from 1 +- this
+const KIT_SPREAD: f32 = 0.1;
 
 impl BoidVec {
-    pub fn random(pos: Vec2f, num: usize) -> BoidVec {
+    pub fn random<R: Rng>(pos: Vec2f, num: usize, rng: &mut R) -> BoidVec {
         let mut boids = BoidVec::with_capacity(num);
-        let mut rng = rand::thread_rng();
 
         for i in 0..num {
             let c = i as f32 / num as f32;
+            let p = pos
+                + Vec2f {
+                    x: rng.gen::<f64>() * SPREAD,
+                    y: rng.gen::<f64>() * SPREAD,
+                };
             boids.push(Boid {
-                pos: pos
-                    + Vec2f {
-                        x: rng.gen::<f64>() * SPREAD,
-                        y: rng.gen::<f64>() * SPREAD,
-                    },
+                pos: p,
+                prev_pos: p,
                 vel: Vec2f {
                     x: rng.gen::<f64>() * VEL_SPREAD - VEL_SPREAD / 2.,
                     y: rng.gen::<f64>() * VEL_SPREAD - VEL_SPREAD / 2.,
@@ -53,15 +137,39 @@ impl BoidVec {
                 r: rng.gen::<f64>(),
                 state: Default::default(),
                 color: [c, c, c, 1.2 - c],
+                kit: 1. + rng.gen_range(-KIT_SPREAD..=KIT_SPREAD),
+                ..Default::default()
             });
         }
 
         boids
     }
 
+    ///Damage to soldier i, better kit takes less of it. Returns true if it killed him
+    pub fn hurt(&mut self, i: usize, damage: f32) -> bool {
+        if self.condition[i] == Condition::Dead {
+            return false;
+        }
+
+        self.health[i] = (self.health[i] - damage / self.kit[i]).max(0.);
+        self.condition[i] = Condition::from_health(self.health[i]);
+        self.condition[i] == Condition::Dead
+    }
+
+    ///Positions of everyone still standing
+    pub fn living(&self) -> impl Iterator<Item = Vec2f> + '_ {
+        self.pos.iter()
+            .zip(self.condition.iter())
+            .filter(|(_, c)| **c != Condition::Dead)
+            .map(|(p, _)| *p)
+    }
+
+    pub fn num_alive(&self) -> usize {
+        self.condition.iter().filter(|c| **c != Condition::Dead).count()
+    }
+
     pub fn zeros(num: usize) -> BoidVec {
         let mut boids = BoidVec::with_capacity(num);
-        let rng = rand::thread_rng();
 
         for _ in 0..num {
             boids.push(Boid::default());
@@ -71,9 +179,75 @@ impl BoidVec {
     }
 }
 
+///Push away from every neighbour closer than radius, stronger the closer it is.
+///Coincident neighbours give no direction and are skipped
+pub fn separation(pos: Vec2f, neighbours: impl Iterator<Item = Vec2f>, radius: f64) -> Vec2f {
+    let mut push = Vec2f::default();
+
+    for other in neighbours {
+        let d = pos - other;
+        let dist = d.len();
+
+        if dist > 0. && dist < radius {
+            push += d * ((radius - dist) / dist);
+        }
+    }
+
+    push
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::boids::{separation, BoidState, BoidVec, Condition};
     use crate::ops::Vec2f;
+    use crate::world::SimRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn same_seed_same_boids() {
+        let a = BoidVec::random(Vec2f::default(), 16, &mut SimRng::seed_from_u64(42));
+        let b = BoidVec::random(Vec2f::default(), 16, &mut SimRng::seed_from_u64(42));
+
+        assert_eq!(a.pos, b.pos);
+        assert_eq!(a.vel, b.vel);
+    }
+
+    #[test]
+    fn separation_pushes_apart() {
+        let a = Vec2f { x: 0., y: 0. };
+        let b = Vec2f { x: 5., y: 0. };
+
+        let push = separation(a, std::iter::once(b), 20.);
+        assert!(push.x < 0.);
+        assert_eq!(push.y, 0.);
+
+        assert_eq!(separation(a, std::iter::once(Vec2f { x: 30., y: 0. }), 20.), Vec2f::default());
+    }
+
+    #[test]
+    fn state_follows_speeds() {
+        assert_eq!(BoidState::classify(0., 0., false), BoidState::Stationary);
+        assert_eq!(BoidState::classify(0., 0., true), BoidState::Idle);
+        assert_eq!(BoidState::classify(10., 100., false), BoidState::Accelerating);
+        assert_eq!(BoidState::classify(100., 100., false), BoidState::Marching);
+        assert_eq!(BoidState::classify(100., 10., false), BoidState::Decelerating);
+    }
+
+    #[test]
+    fn wounds_then_kills() {
+        let mut boids = BoidVec::zeros(2);
+        boids.kit[1] = 2.;
+
+        assert!(!boids.hurt(0, 0.6));
+        assert_eq!(boids.condition[0], Condition::Wounded);
+        assert!(!boids.hurt(1, 0.6));
+        assert_eq!(boids.condition[1], Condition::Fit);
+
+        assert!(boids.hurt(0, 0.6));
+        assert_eq!(boids.health[0], 0.);
+        assert!(!boids.hurt(0, 1.));
+        assert_eq!(boids.num_alive(), 1);
+    }
 
     #[test]
     fn test() {
@@ -0,0 +1,269 @@
+use crate::ops::Vec2f;
+use crate::traits::Controllable;
+use crate::units::{share_front, share_move, Goal, Member, COMPANY_GAP};
+use crate::world::{World, WorldId};
+use petgraph::stable_graph::{NodeIndex, StableDiGraph};
+use petgraph::visit::{Dfs, DfsPostOrder};
+use petgraph::Direction;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Echelon {
+    Army,
+    Legion,
+    Cohort,
+    Century,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandNode {
+    pub name: String,
+    pub echelon: Echelon,
+    ///the company a century commands, None for every level above
+    pub company: Option<WorldId>,
+
+    ///men left under the node, aggregated up every step
+    #[serde(default)]
+    pub strength: usize,
+    ///mean of the companies under the node, weighted by strength
+    #[serde(default)]
+    pub morale: f32,
+}
+
+impl CommandNode {
+    pub fn new(name: String, echelon: Echelon, company: Option<WorldId>) -> Self {
+        CommandNode {
+            name,
+            echelon,
+            company,
+            strength: 0,
+            morale: 0.,
+        }
+    }
+}
+
+///Chain of command laid over the world's companies, however they are grouped in World::groups.
+///Edges run from a commander to a subordinate, the companies hang off the leaves
+#[derive(Default, Serialize, Deserialize)]
+pub struct Hierarchy {
+    pub graph: StableDiGraph<CommandNode, ()>,
+}
+
+impl Hierarchy {
+    pub fn add(&mut self, superior: Option<NodeIndex>, node: CommandNode) -> NodeIndex {
+        let n = self.graph.add_node(node);
+        if let Some(superior) = superior {
+            self.graph.add_edge(superior, n, ());
+        }
+        n
+    }
+
+    pub fn superior(&self, n: NodeIndex) -> Option<NodeIndex> {
+        self.graph.neighbors_directed(n, Direction::Incoming).next()
+    }
+
+    ///Direct subordinates in the order they were added
+    pub fn subordinates(&self, n: NodeIndex) -> Vec<NodeIndex> {
+        let mut subs: Vec<NodeIndex> = self.graph.neighbors_directed(n, Direction::Outgoing).collect();
+        //petgraph walks the newest edge first
+        subs.reverse();
+        subs
+    }
+
+    pub fn roots(&self) -> Vec<NodeIndex> {
+        self.graph.node_indices().filter(|n| self.superior(*n).is_none()).collect()
+    }
+
+    pub fn node_of(&self, company: WorldId) -> Option<NodeIndex> {
+        self.graph.node_indices().find(|n| self.graph[*n].company == Some(company))
+    }
+
+    ///Every company commanded from n
+    pub fn companies_under(&self, n: NodeIndex) -> Vec<WorldId> {
+        let mut ids = vec![];
+        let mut dfs = Dfs::new(&self.graph, n);
+        while let Some(m) = dfs.next(&self.graph) {
+            if let Some(id) = self.graph[m].company {
+                ids.push(id);
+            }
+        }
+        ids
+    }
+
+    ///Lowest node whose subtree holds every company in ids and more besides,
+    ///what selecting one echelon up gives. Ids outside the hierarchy are ignored
+    pub fn widen(&self, ids: &[WorldId]) -> Option<NodeIndex> {
+        let ids: Vec<WorldId> = ids.iter().copied().filter(|id| self.node_of(*id).is_some()).collect();
+        let mut n = self.node_of(*ids.first()?)?;
+
+        loop {
+            let under = self.companies_under(n);
+            if under.len() > ids.len() && ids.iter().all(|id| under.contains(id)) {
+                return Some(n);
+            }
+            n = self.superior(n)?;
+        }
+    }
+
+    ///Strength and morale of every node from (strength, morale) of the companies under it
+    pub fn aggregate(&mut self, companies: &HashMap<WorldId, (usize, f32)>) {
+        for root in self.roots() {
+            let mut post = DfsPostOrder::new(&self.graph, root);
+            while let Some(n) = post.next(&self.graph) {
+                let (strength, morale) = match self.graph[n].company {
+                    Some(id) => companies.get(&id).copied().unwrap_or((0, 0.)),
+                    None => {
+                        let mut strength = 0;
+                        let mut weighted = 0.;
+                        for s in self.graph.neighbors_directed(n, Direction::Outgoing) {
+                            strength += self.graph[s].strength;
+                            weighted += self.graph[s].morale * self.graph[s].strength as f32;
+                        }
+                        (strength, if strength > 0 { weighted / strength as f32 } else { 0. })
+                    }
+                };
+
+                let node = &mut self.graph[n];
+                node.strength = strength;
+                node.morale = morale;
+            }
+        }
+    }
+
+    ///A node seen from its commander: centre of its companies, men in them and the frontage they form abreast
+    pub fn member(&self, n: NodeIndex, world: &World) -> Member {
+        let companies: Vec<Member> = self.companies_under(n)
+            .into_iter()
+            .filter_map(|id| world.find_company(id))
+            .map(Member::of)
+            .collect();
+
+        let mut center = Vec2f::default();
+        for m in companies.iter() {
+            center += m.center;
+        }
+        if !companies.is_empty() {
+            center = center * (1. / companies.len() as f64);
+        }
+
+        Member {
+            center,
+            strength: companies.iter().map(|m| m.strength).sum(),
+            frontage: companies.iter().map(|m| m.frontage).sum::<f64>()
+                + COMPANY_GAP * companies.len().saturating_sub(1) as f64,
+        }
+    }
+
+    ///Passes a goal for n down the chain, every level sharing it out among its subordinates.
+    ///Returns one goal per company
+    pub fn distribute(&self, n: NodeIndex, goal: Goal, world: &World) -> Vec<(WorldId, Goal)> {
+        if let Some(id) = self.graph[n].company {
+            return vec![(id, goal)];
+        }
+
+        let subs = self.subordinates(n);
+        let members: Vec<Member> = subs.iter().map(|s| self.member(*s, world)).collect();
+        let center = self.member(n, world).center;
+
+        let goals = match &goal {
+            Goal::Front(p1, p2, dir) => share_front(*p1, *p2, *dir, &members),
+            Goal::Move(pos, dir) => share_move(*pos, *dir, &members),
+            Goal::Path(path, dir) => share_move(path.end(), *dir, &members),
+            Goal::Face(dir) => share_move(center, *dir, &members),
+            //subordinates keep where they stand relative to each other
+            Goal::Idle(pos) => members.iter().map(|m| Goal::Idle(*pos + m.center - center)).collect(),
+            Goal::Column(pos, deploy) => members.iter()
+                .map(|m| Goal::Column(*pos + m.center - center, *deploy))
+                .collect(),
+            Goal::Hold => vec![Goal::Hold; subs.len()],
+        };
+
+        subs.into_iter()
+            .zip(goals)
+            .flat_map(|(s, g)| self.distribute(s, g, world))
+            .collect()
+    }
+}
+
+///Orders for a whole subtree of the hierarchy, so they go through the same Controllable calls as a company's
+pub struct CommandOrder<'a> {
+    pub world: &'a mut World,
+    pub node: NodeIndex,
+}
+
+impl CommandOrder<'_> {
+    fn issue(&mut self, goal: Goal, queue: bool) {
+        let world: &World = self.world;
+        let orders = world.command.distribute(self.node, goal, world);
+
+        for (id, goal) in orders {
+            if let Some(company) = self.world.find_company_mut(id) {
+                if queue {
+                    company.add_order(goal);
+                } else {
+                    company.new_order(goal);
+                }
+            }
+        }
+    }
+}
+
+impl Controllable for CommandOrder<'_> {
+    fn new_order(&mut self, goal: Goal) {
+        self.issue(goal, false);
+    }
+
+    fn add_order(&mut self, goal: Goal) {
+        self.issue(goal, true);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::{CommandNode, Echelon, Hierarchy};
+    use std::collections::HashMap;
+
+    fn century(h: &mut Hierarchy, cohort: petgraph::stable_graph::NodeIndex, id: usize) {
+        h.add(Some(cohort), CommandNode::new(format!("century {}", id), Echelon::Century, Some(id)));
+    }
+
+    #[test]
+    fn status_aggregates_up_and_selection_widens() {
+        let mut h = Hierarchy::default();
+        let legion = h.add(None, CommandNode::new("legion".into(), Echelon::Legion, None));
+        let first = h.add(Some(legion), CommandNode::new("cohort 1".into(), Echelon::Cohort, None));
+        let second = h.add(Some(legion), CommandNode::new("cohort 2".into(), Echelon::Cohort, None));
+        century(&mut h, first, 256);
+        century(&mut h, first, 512);
+        century(&mut h, second, 768);
+
+        let stats = HashMap::from([(256, (30, 1.)), (512, (10, 0.)), (768, (40, 0.5))]);
+        h.aggregate(&stats);
+
+        assert_eq!(h.graph[first].strength, 40);
+        assert!((h.graph[first].morale - 0.75).abs() < 1e-6);
+        assert_eq!(h.graph[legion].strength, 80);
+        assert!((h.graph[legion].morale - 0.625).abs() < 1e-6);
+
+        assert_eq!(h.widen(&[256]), Some(first));
+        assert_eq!(h.widen(&[256, 512]), Some(legion));
+        assert_eq!(h.widen(&[256, 512, 768]), None);
+        assert_eq!(h.subordinates(legion), vec![first, second]);
+    }
+
+    #[test]
+    fn hierarchy_round_trips() {
+        let mut h = Hierarchy::default();
+        let army = h.add(None, CommandNode::new("army".into(), Echelon::Army, None));
+        let legion = h.add(Some(army), CommandNode::new("legion".into(), Echelon::Legion, None));
+        century(&mut h, legion, 256);
+
+        let text = ron::to_string(&h).unwrap();
+        let back: Hierarchy = ron::from_str(&text).unwrap();
+
+        assert_eq!(back.graph.node_count(), 3);
+        assert_eq!(back.companies_under(army), vec![256]);
+        assert_eq!(back.superior(legion), Some(army));
+    }
+}
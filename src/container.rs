@@ -4,7 +4,6 @@ use rand::Rng;
 use std::collections::VecDeque;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
-use crate::app::CLICK_PRECISION;
 use crate::boids::BoidState::{Marching, Stationary};
 use serde::de::Error;
 use serde::{Deserialize, Deserializer, Serialize};
@@ -14,16 +13,18 @@ use crate::container::ContainerState::Cold;
 /// Warm allows collision checks
 /// Hot allows per-unit operations and info
 
-#[derive(PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ContainerState {
     Cold,
     Warm,
+    #[default]
     Hot,
 }
 
 use crate::ops::Vec2f;
 use crate::player::{PlayerAction, PlayerState};
-use crate::world::{Identifiable, WORLD_ID, WorldId};
+use crate::traits::Identifiable;
+use crate::world::{WORLD_ID, WorldId};
 
 pub trait Container {
     fn cur_state(&self) -> ContainerState {
@@ -41,7 +42,7 @@ pub trait Container {
 mod tests {
     //use crate::boids::BoidVec;
     use crate::ops::Vec2f;
-    use crate::world::Identifiable;
+    use crate::traits::Identifiable;
     use quickcheck::quickcheck;
     use std::collections::VecDeque;
 
@@ -1,38 +1,45 @@
 extern crate lazy_static;
-use crate::boids::BoidRef;
+use crate::boids::{BoidRef, Condition};
 use crate::container::{Container};
 use crate::ops::Vec2f;
 use graphics::types::Rectangle;
 use graphics::*;
 use lazy_static::lazy_static;
 use std::sync::atomic::AtomicPtr;
-use crate::units::Goal;
+use crate::units::{BasicUnit, Goal, GOAL_MARKER_SIZE};
 
 pub trait Drawable {
-    fn draw<G>(&self, c: Context, g: &mut G)
+    ///alpha is the fraction of a tick elapsed since the last simulation step
+    fn draw<G>(&self, c: Context, g: &mut G, alpha: f64)
     where
         G: Graphics;
 }
 
 const BOID_SIZE: f64 = 24.;
+///the fallen are drawn faded
+const DEAD_ALPHA: f32 = 0.3;
 
 lazy_static! {
     static ref boid_square: Rectangle = rectangle::square(0.0, 0.0, BOID_SIZE);
 }
 
 impl Drawable for BoidRef<'_> {
-    fn draw<G>(&self, c: Context, g: &mut G)
+    fn draw<G>(&self, c: Context, g: &mut G, alpha: f64)
     where
         G: Graphics,
     {
-        println!("draw");
+        let pos = *self.prev_pos + (*self.pos - *self.prev_pos) * alpha;
         let transform = c
             .transform
-            .trans(self.pos.x, self.pos.y)
+            .trans(pos.x, pos.y)
             .rot_rad(*self.r)
             .trans(-BOID_SIZE / 2., -BOID_SIZE / 2.);
 
-        rectangle(*self.color, *boid_square, transform, g);
+        let mut color = *self.color;
+        if *self.condition == Condition::Dead {
+            color[3] *= DEAD_ALPHA;
+        }
+        rectangle(color, *boid_square, transform, g);
     }
 }
 
@@ -46,14 +53,15 @@ const TRANSP_BLUE: [f32; 4] = [0.0, 0.0, 1.0, 0.2];
 const TRANSP_ORANGE: [f32; 4] = [0.5, 0.1, 0.0, 0.1];
 const TRANSP_RED: [f32; 4] = [0.9, 0.1, 0.0, 0.1];
 
-impl Drawable for Container {
-    fn draw<G>(&self, c: Context, g: &mut G)
+impl Drawable for BasicUnit {
+    fn draw<G>(&self, c: Context, g: &mut G, alpha: f64)
     where
         G: Graphics,
     {
-        for boid in self.ent.iter() {
-            //let b: Boid = boid;
-            boid.draw(c, g);
+        if let Some(troops) = &self.troops {
+            for boid in troops.iter() {
+                boid.draw(c, g, alpha);
+            }
         }
 
         for pos in self.formation_positions.iter() {
@@ -66,7 +74,7 @@ impl Drawable for Container {
 
         let transform = c.transform.trans(self.center.x, self.center.y);
 
-        let self_area = ellipse::circle(0., 0., self.radius);
+        let self_area = ellipse::circle(0., 0., self.select_radius);
 
         if self.selected {
             ellipse(TRANSP_RED, self_area, transform, g);
@@ -74,29 +82,120 @@ impl Drawable for Container {
             ellipse(TRANSP_ORANGE, self_area, transform, g);
         }
 
-        if let Some(goal) = self.goals.front() {
-            let transform = c
-                .transform
-                .trans(-CURSOR_SIZE / 2., -CURSOR_SIZE / 2.);
+        draw_fatigue_bar(self, c, g);
+
+        //the front goal is shown for every unit, the whole queue for selected ones
+        let shown = if self.selected { self.goals.len() } else { 1 };
+        let transform = c
+            .transform
+            .trans(-CURSOR_SIZE / 2., -CURSOR_SIZE / 2.);
 
+        for goal in self.goals.iter().take(shown) {
             match goal {
                 Goal::Idle(_) => {}
                 Goal::Hold => {}
                 Goal::Move(_, _) => {}
-                Goal::Column(_) => {}
-                Goal::Front(p1, p2, d) => {
+                Goal::Face(_) => {}
+                Goal::Path(path, _) => {
+                    for (a, b) in path.samples().iter().zip(path.samples().iter().skip(1)) {
+                        line_from_to(TRANSP_RED, 3., *a, *b, transform, g);
+                    }
+                }
+                Goal::Column(_, _) => {}
+                Goal::Front(p1, p2, _) => {
                     line_from_to(TRANSP_RED, 5., *p1, *p2, transform, g);
-                    line_from_to(
-                        TRANSP_RED,
-                        5.,
-                        self.center,
-                        self.center + *d,
-                        transform,
-                        g,
-                    );
                 }
             }
         }
+
+        if self.selected {
+            draw_goal_markers(self, c, g);
+        }
+    }
+}
+
+const BAR_WIDTH: f64 = 40.;
+const BAR_HEIGHT: f64 = 4.;
+
+///Stamina left over the company, green when fresh, red when spent
+fn draw_fatigue_bar<G>(unit: &BasicUnit, c: Context, g: &mut G)
+where
+    G: Graphics,
+{
+    let transform = c
+        .transform
+        .trans(unit.center.x - BAR_WIDTH / 2., unit.center.y - unit.select_radius - 2. * BAR_HEIGHT);
+
+    let fatigue = unit.fatigue.clamp(0., 1.);
+    let left = [0.1 + 0.8 * fatigue, 0.9 - 0.8 * fatigue, 0.1, 1.0];
+
+    rectangle(BLACK, [0., 0., BAR_WIDTH, BAR_HEIGHT], transform, g);
+    rectangle(left, [0., 0., BAR_WIDTH * (1. - fatigue as f64), BAR_HEIGHT], transform, g);
+}
+
+const ARROW_LEN: f64 = 24.;
+const ARROW_HEAD_ANGLE: f64 = 2.6;
+const PIP_SIZE: f64 = 2.;
+
+///Queued goals in order as markers joined by lines, with an arrow for the facing each one ends in.
+///There is no font loaded, so a marker's number is shown as pips above it
+fn draw_goal_markers<G>(unit: &BasicUnit, c: Context, g: &mut G)
+where
+    G: Graphics,
+{
+    let marker = ellipse::circle(0., 0., GOAL_MARKER_SIZE);
+    let pip = ellipse::circle(0., 0., PIP_SIZE);
+
+    let mut at = unit.center;
+    let mut n = 0;
+    for goal in unit.goals.iter().filter(|g| !g.is_standing()) {
+        if let Some(pos) = goal.target() {
+            //paths draw their own curve
+            if !matches!(goal, Goal::Path(..)) {
+                line_from_to(TRANSP_BLUE, 1., at, pos, c.transform, g);
+            }
+            ellipse(TRANSP_BLUE, marker, c.transform.trans(pos.x, pos.y), g);
+
+            n += 1;
+            let row = (n - 1) as f64 * PIP_SIZE * 1.5;
+            for k in 0..n {
+                let x = pos.x - row + k as f64 * PIP_SIZE * 3.;
+                ellipse(BLACK, pip, c.transform.trans(x, pos.y - GOAL_MARKER_SIZE - PIP_SIZE * 2.), g);
+            }
+            at = pos;
+        }
+
+        if let Some(dir) = goal.facing() {
+            draw_arrow(BLUE, at, dir, c, g);
+        }
+    }
+}
+
+fn draw_arrow<G>(color: [f32; 4], from: Vec2f, dir: Vec2f, c: Context, g: &mut G)
+where
+    G: Graphics,
+{
+    if dir.len() == 0. {
+        return;
+    }
+
+    let dir = dir.normalise();
+    let tip = from + dir * ARROW_LEN;
+    line_from_to(color, 1., from, tip, c.transform, g);
+    for side in &[-1., 1.] {
+        line_from_to(color, 1., tip, tip + dir.rotate(side * ARROW_HEAD_ANGLE) * (ARROW_LEN / 3.), c.transform, g);
+    }
+}
+
+///Translucent marker on every slot of a formation that is being dragged out
+pub fn draw_ghost<G>(slots: &[Vec2f], c: Context, g: &mut G)
+where
+    G: Graphics,
+{
+    let ghost = ellipse::circle(0., 0., BOID_SIZE / 2.);
+
+    for pos in slots {
+        ellipse(TRANSP_BLUE, ghost, c.transform.trans(pos.x, pos.y), g);
     }
 }
 
@@ -0,0 +1,239 @@
+use crate::formations::Formation;
+use crate::ops::Vec2f;
+use crate::traits::Controllable;
+use crate::units::{BasicUnit, Goal};
+use serde::{Deserialize, Serialize};
+
+///One company taking up a formation at a place relative to the battalion.
+///pos and dir are in the battalion's frame as it stood when the drill began: x ahead, y across
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DrillStep {
+    ///which company of the battalion, by its place in troops
+    pub company_type_id: usize,
+    pub company_formation: Formation,
+    pub pos: Vec2f,
+    pub dir: Vec2f,
+    ///seconds the step's group has to finish in. A step with 0 is issued together with the next one,
+    ///a group ending the drill on 0 is waited on without a deadline
+    pub time: f32,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Drill {
+    #[serde(default)]
+    pub name: String,
+    pub steps: Vec<DrillStep>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DrillFailure {
+    ///the step names a company the battalion does not have, or one with nobody left in it
+    CompanyLost,
+    ///the companies were not formed in place when the step's time ran out
+    OutOfTime,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DrillStatus {
+    Running,
+    Finished,
+    Failed { step: usize, reason: DrillFailure },
+}
+
+///A drill being played against a battalion's companies.
+///Steps are issued in groups, each group ending on a step with a time; the next group waits until
+///every company of this one has formed where it was sent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DrillRun {
+    ///index into known_drills of the battalion
+    pub drill: usize,
+    origin: Vec2f,
+    facing: Vec2f,
+    ///next step to issue
+    step: usize,
+    ///companies the current group waits on
+    pending: Vec<usize>,
+    ///None while the group has no deadline
+    time_left: Option<f32>,
+    pub status: DrillStatus,
+}
+
+impl DrillRun {
+    pub fn new(drill: usize, origin: Vec2f, facing: Vec2f) -> Self {
+        DrillRun {
+            drill,
+            origin,
+            facing,
+            step: 0,
+            pending: vec![],
+            time_left: None,
+            status: DrillStatus::Running,
+        }
+    }
+
+    pub fn advance(&mut self, drill: &Drill, companies: &mut [BasicUnit], dt: f64) -> DrillStatus {
+        if self.status != DrillStatus::Running {
+            return self.status;
+        }
+
+        if !self.pending.is_empty() {
+            let done = self.pending.iter().all(|i| companies.get(*i).map_or(false, in_place));
+            if !done {
+                if let Some(time_left) = &mut self.time_left {
+                    *time_left -= dt as f32;
+                    if *time_left < 0. {
+                        self.fail(self.step - 1, DrillFailure::OutOfTime);
+                    }
+                }
+                return self.status;
+            }
+            self.pending.clear();
+        }
+
+        if self.step == drill.steps.len() {
+            self.status = DrillStatus::Finished;
+            return self.status;
+        }
+
+        //issue steps up to and including the next one that is waited on
+        self.time_left = None;
+        while let Some(step) = drill.steps.get(self.step) {
+            let company = match companies.get_mut(step.company_type_id) {
+                Some(company) if company.num_alive() > 0 => company,
+                _ => {
+                    self.fail(self.step, DrillFailure::CompanyLost);
+                    return self.status;
+                }
+            };
+
+            company.formation = step.company_formation;
+            company.new_order(Goal::Move(
+                self.origin + step.pos.rot_align(self.facing),
+                step.dir.rot_align(self.facing),
+            ));
+            self.pending.push(step.company_type_id);
+
+            self.step += 1;
+            if step.time > 0. {
+                self.time_left = Some(step.time);
+                break;
+            }
+        }
+
+        self.status
+    }
+
+    fn fail(&mut self, step: usize, reason: DrillFailure) {
+        self.status = DrillStatus::Failed { step, reason };
+    }
+}
+
+///Standing on its slots with nothing left to do
+fn in_place(company: &BasicUnit) -> bool {
+    company.goals.front().map_or(true, Goal::is_standing) && company.is_formed()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::drill::{Drill, DrillFailure, DrillRun, DrillStatus, DrillStep};
+    use crate::formations::Formation;
+    use crate::grid::SpatialGrid;
+    use crate::ops::Vec2f;
+    use crate::traits::Controllable;
+    use crate::units::{BasicUnit, Goal};
+    use crate::world::{SimRng, TICK};
+    use rand::SeedableRng;
+
+    fn step(company: usize, x: i32, time: f32) -> DrillStep {
+        DrillStep {
+            company_type_id: company,
+            company_formation: Formation::phalanx(4),
+            pos: Vec2f::from((x, 0)),
+            dir: Vec2f::from((1, 0)),
+            time,
+        }
+    }
+
+    #[test]
+    fn steps_are_placed_in_the_battalion_frame() {
+        let mut rng = SimRng::seed_from_u64(0);
+        let mut companies = vec![BasicUnit::new(Vec2f::default(), 8, &mut rng)];
+        let drill = Drill { name: String::new(), steps: vec![step(0, 100, 10.)] };
+
+        //battalion facing down the y axis
+        let mut run = DrillRun::new(0, Vec2f::from((50, 0)), Vec2f::from((0, 1)));
+        assert_eq!(run.advance(&drill, &mut companies, 0.1), DrillStatus::Running);
+
+        match companies[0].goals.front() {
+            Some(Goal::Move(pos, dir)) => {
+                assert!((*pos - Vec2f::from((50, 100))).len() < 1e-9);
+                assert!((*dir - Vec2f::from((0, 1))).len() < 1e-9);
+            }
+            other => panic!("expected a move, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn drill_fails_on_time_and_missing_companies() {
+        let mut rng = SimRng::seed_from_u64(0);
+        let mut companies = vec![BasicUnit::new(Vec2f::default(), 8, &mut rng)];
+
+        let drill = Drill { name: String::new(), steps: vec![step(0, 500, 0.5)] };
+        let mut run = DrillRun::new(0, Vec2f::default(), Vec2f::from((1, 0)));
+        run.advance(&drill, &mut companies, 0.1);
+        run.advance(&drill, &mut companies, 1.);
+        assert_eq!(run.status, DrillStatus::Failed { step: 0, reason: DrillFailure::OutOfTime });
+
+        let drill = Drill { name: String::new(), steps: vec![step(0, 0, 0.), step(3, 0, 1.)] };
+        let mut run = DrillRun::new(0, Vec2f::default(), Vec2f::from((1, 0)));
+        run.advance(&drill, &mut companies, 0.1);
+        assert_eq!(run.status, DrillStatus::Failed { step: 1, reason: DrillFailure::CompanyLost });
+    }
+
+    #[test]
+    fn trailing_untimed_group_has_no_deadline() {
+        let mut rng = SimRng::seed_from_u64(0);
+        let mut companies = vec![
+            BasicUnit::new(Vec2f::default(), 8, &mut rng),
+            BasicUnit::new(Vec2f::from((0, 100)), 8, &mut rng),
+        ];
+
+        let drill = Drill { name: String::new(), steps: vec![step(0, 500, 0.), step(1, 500, 0.)] };
+        let mut run = DrillRun::new(0, Vec2f::default(), Vec2f::from((1, 0)));
+        for _ in 0..10 {
+            run.advance(&drill, &mut companies, 1.);
+        }
+        assert_eq!(run.status, DrillStatus::Running);
+
+        //formed in place, the drill ends
+        for company in companies.iter_mut() {
+            company.new_order(Goal::Hold);
+            company.formation_positions = company.troops.as_ref().unwrap().pos.clone();
+        }
+        assert_eq!(run.advance(&drill, &mut companies, 1.), DrillStatus::Finished);
+    }
+
+    #[test]
+    fn the_dead_do_not_hold_up_the_drill() {
+        let mut rng = SimRng::seed_from_u64(0);
+        let mut companies = vec![BasicUnit::new(Vec2f::default(), 16, &mut rng)];
+        let troops = companies[0].troops.as_mut().unwrap();
+        for i in 0..5 {
+            troops.hurt(i, 10.);
+        }
+
+        let drill = Drill { name: String::new(), steps: vec![step(0, 300, 30.)] };
+        let mut run = DrillRun::new(0, Vec2f::default(), Vec2f::from((1, 0)));
+        let index = SpatialGrid::new(32.);
+        for _ in 0..(40. / TICK) as usize {
+            if run.advance(&drill, &mut companies, TICK) != DrillStatus::Running {
+                break;
+            }
+            companies[0].p_b(TICK, &mut rng, &index);
+        }
+
+        assert_eq!(run.status, DrillStatus::Finished);
+        //the front rank stands on the step's position, the living block just behind it
+        assert!((companies[0].center - Vec2f::from((300, 0))).len() < 40.);
+    }
+}
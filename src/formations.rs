@@ -1,26 +1,182 @@
-use boids::ops::Vec2f;
+use crate::ops::Vec2f;
+use crate::world::SimRng;
 use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
 
 pub const FORMATION_SPACING: f64 = 24.;
+///testudo shields overlap, still further apart than DIST_REPEL
+pub const CLOSE_SPACING: f64 = 21.;
+pub const LOOSE_SPACING: f64 = 48.;
 
-pub type FormationFunction = fn(usize, usize) -> Vec2f;
+///Slot layout of a company. Offsets are in the unit's local frame:
+///x runs along the front centred on 0, y is depth behind the front rank.
+///Every variant with a front takes its frontage from width files at spacing
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Formation {
+    ///random placement within one spacing square
+    Scatter,
+    ///width files across the front, ranks rank_depth apart
+    Phalanx {
+        width: usize,
+        spacing: f64,
+        rank_depth: f64,
+    },
+    ///loose crowd around the anchor
+    Idle { radius: f64 },
+    ///cuneus, point forward, widening each rank until the frontage is reached
+    Wedge { width: usize, spacing: f64 },
+    ///square with frontage long sides, filled from the outside in
+    HollowSquare { width: usize, spacing: f64 },
+    ///orbis, concentric rings facing outwards, frontage is the diameter
+    Orb { width: usize, spacing: f64 },
+    ///close order block
+    Testudo { width: usize, spacing: f64 },
+    ///loose order, alternate ranks offset by half a file
+    Skirmish { width: usize, spacing: f64 },
+    ///line with every file step further back than the one to its left
+    EchelonLeft { width: usize, spacing: f64, step: f64 },
+    ///line with every file step further back than the one to its right
+    EchelonRight { width: usize, spacing: f64, step: f64 },
+}
+
+impl Default for Formation {
+    fn default() -> Self {
+        Formation::Scatter
+    }
+}
+
+impl Formation {
+    pub fn phalanx(width: usize) -> Self {
+        Formation::Phalanx {
+            width: width.max(1),
+            spacing: FORMATION_SPACING,
+            rank_depth: FORMATION_SPACING,
+        }
+    }
+
+    pub fn wedge(width: usize) -> Self {
+        Formation::Wedge { width: width.max(1), spacing: FORMATION_SPACING }
+    }
+
+    pub fn hollow_square(width: usize) -> Self {
+        Formation::HollowSquare { width: width.max(1), spacing: FORMATION_SPACING }
+    }
+
+    pub fn orb(width: usize) -> Self {
+        Formation::Orb { width: width.max(1), spacing: FORMATION_SPACING }
+    }
+
+    pub fn testudo(width: usize) -> Self {
+        Formation::Testudo { width: width.max(1), spacing: CLOSE_SPACING }
+    }
+
+    pub fn skirmish(width: usize) -> Self {
+        Formation::Skirmish { width: width.max(1), spacing: LOOSE_SPACING }
+    }
+
+    pub fn echelon_left(width: usize) -> Self {
+        Formation::EchelonLeft { width: width.max(1), spacing: FORMATION_SPACING, step: FORMATION_SPACING / 2. }
+    }
+
+    pub fn echelon_right(width: usize) -> Self {
+        Formation::EchelonRight { width: width.max(1), spacing: FORMATION_SPACING, step: FORMATION_SPACING / 2. }
+    }
+
+    ///Number of files, never less than 1 so a deserialized width of 0 can't divide by zero
+    pub fn width(&self) -> usize {
+        match *self {
+            Formation::Phalanx { width, .. }
+            | Formation::Wedge { width, .. }
+            | Formation::HollowSquare { width, .. }
+            | Formation::Orb { width, .. }
+            | Formation::Testudo { width, .. }
+            | Formation::Skirmish { width, .. }
+            | Formation::EchelonLeft { width, .. }
+            | Formation::EchelonRight { width, .. } => width.max(1),
+            Formation::Scatter | Formation::Idle { .. } => 1,
+        }
+    }
+
+    pub fn set_width(&mut self, w: usize) {
+        match self {
+            Formation::Phalanx { width, .. }
+            | Formation::Wedge { width, .. }
+            | Formation::HollowSquare { width, .. }
+            | Formation::Orb { width, .. }
+            | Formation::Testudo { width, .. }
+            | Formation::Skirmish { width, .. }
+            | Formation::EchelonLeft { width, .. }
+            | Formation::EchelonRight { width, .. } => *width = w.max(1),
+            Formation::Scatter | Formation::Idle { .. } => {}
+        }
+    }
 
-pub fn default_formation(index: usize, _: usize) -> Vec2f {
-    let mut rng = rand::thread_rng();
+    pub fn spacing(&self) -> f64 {
+        match *self {
+            Formation::Phalanx { spacing, .. }
+            | Formation::Wedge { spacing, .. }
+            | Formation::HollowSquare { spacing, .. }
+            | Formation::Orb { spacing, .. }
+            | Formation::Testudo { spacing, .. }
+            | Formation::Skirmish { spacing, .. }
+            | Formation::EchelonLeft { spacing, .. }
+            | Formation::EchelonRight { spacing, .. } => spacing,
+            Formation::Scatter | Formation::Idle { .. } => FORMATION_SPACING,
+        }
+    }
+
+    ///Distance between the outermost files
+    pub fn frontage(&self) -> f64 {
+        self.width().saturating_sub(1) as f64 * self.spacing()
+    }
+
+    ///Distance from the front rank to the last, for num boids in ranks of width
+    pub fn depth(&self, num: usize) -> f64 {
+        (num.saturating_sub(1) / self.width()) as f64 * self.spacing()
+    }
+
+    ///Slots for num boids in the local frame
+    pub fn offsets(&self, num: usize, rng: &mut SimRng) -> Vec<Vec2f> {
+        let frontage = self.frontage();
+
+        match *self {
+            Formation::Scatter => (0..num).map(|i| default_formation(i, rng)).collect(),
+            Formation::Phalanx { width, spacing, rank_depth } => {
+                (0..num).map(|i| phalanx_formation(i, width, spacing, rank_depth)).collect()
+            }
+            Formation::Idle { radius } => (0..num).map(|_| idle_formation(radius, rng)).collect(),
+            Formation::Wedge { spacing, .. } => wedge_formation(num, frontage, spacing),
+            Formation::HollowSquare { spacing, .. } => hollow_square_formation(num, frontage, spacing),
+            Formation::Orb { spacing, .. } => orb_formation(num, frontage, spacing),
+            Formation::Testudo { width, spacing } => {
+                (0..num).map(|i| phalanx_formation(i, width, spacing, spacing)).collect()
+            }
+            Formation::Skirmish { spacing, .. } => skirmish_formation(num, frontage, spacing),
+            Formation::EchelonLeft { spacing, step, .. } => echelon_formation(num, frontage, spacing, step),
+            Formation::EchelonRight { spacing, step, .. } => echelon_formation(num, frontage, spacing, -step),
+        }
+    }
+}
+
+///Files that fit in a frontage, at least one
+fn files(frontage: f64, spacing: f64) -> usize {
+    (frontage / spacing).round() as usize + 1
+}
+
+pub fn default_formation(index: usize, rng: &mut SimRng) -> Vec2f {
     Vec2f {
-        x: rng.gen::<f64>() * FORMATION_SPACING,
+        x: (rng.gen::<f64>() - 0.5) * FORMATION_SPACING,
         y: rng.gen::<f64>() * FORMATION_SPACING,
     }
 }
 
-pub fn crutch() -> FormationFunction {
-    default_formation
-}
+pub fn phalanx_formation(index: usize, width: usize, spacing: f64, rank_depth: f64) -> Vec2f {
+    let half_front = width.saturating_sub(1) as f64 / 2.;
 
-pub fn phalanx_formation(index: usize, width: usize) -> Vec2f {
     Vec2f {
-        x: index.checked_rem(width).unwrap_or_default() as f64,
-        y: index.checked_div(width).unwrap_or_default() as f64,
+        x: (index.checked_rem(width).unwrap_or_default() as f64 - half_front) * spacing,
+        y: index.checked_div(width).unwrap_or_default() as f64 * rank_depth,
     }
 }
 
@@ -31,18 +187,266 @@ pub fn p_f(index: usize, width: usize, xdir_norm: Vec2f, ydir_norm: Vec2f) -> Ve
     xdir_norm * x + ydir_norm * y * FORMATION_SPACING
 }
 
-pub fn idle_formation(index: usize, width: usize) -> Vec2f {
-    let mut rng = rand::thread_rng();
-    let mut x = rng.gen::<f64>();
-    let mut y = rng.gen::<f64>();
+pub fn idle_formation(radius: f64, rng: &mut SimRng) -> Vec2f {
+    let mut x = rng.gen::<f64>() * 2. - 1.;
+    let mut y = rng.gen::<f64>() * 2. - 1.;
 
     while x * x + y * y > 1. {
-        x = rng.gen::<f64>();
-        y = rng.gen::<f64>();
+        x = rng.gen::<f64>() * 2. - 1.;
+        y = rng.gen::<f64>() * 2. - 1.;
     }
 
     Vec2f {
-        x: x * FORMATION_SPACING,
-        y: y * FORMATION_SPACING,
+        x: x * radius,
+        y: y * radius,
+    }
+}
+
+pub fn wedge_formation(num: usize, frontage: f64, spacing: f64) -> Vec<Vec2f> {
+    let max_files = files(frontage, spacing);
+    let mut slots = Vec::with_capacity(num);
+    let mut rank = 0;
+
+    while slots.len() < num {
+        let in_rank = (2 * rank + 1).min(max_files).min(num - slots.len());
+        let half = (in_rank - 1) as f64 / 2.;
+
+        for f in 0..in_rank {
+            slots.push(Vec2f {
+                x: (f as f64 - half) * spacing,
+                y: rank as f64 * spacing,
+            });
+        }
+        rank += 1;
+    }
+
+    slots
+}
+
+pub fn hollow_square_formation(num: usize, frontage: f64, spacing: f64) -> Vec<Vec2f> {
+    let mut slots = Vec::with_capacity(num);
+    //the layers have to hold everyone, widen the square if they can't
+    let frontage = frontage.max((num as f64).sqrt().ceil() * spacing);
+    let half = frontage / 2.;
+    let mut layer = 0;
+
+    while slots.len() < num {
+        let inset = layer as f64 * spacing;
+        let side = frontage - 2. * inset;
+        let per_side = (side / spacing).round() as usize;
+
+        if per_side == 0 {
+            //whatever is left stands in the middle
+            while slots.len() < num {
+                slots.push(Vec2f { x: 0., y: half });
+            }
+            break;
+        }
+
+        let step = side / per_side as f64;
+        //clockwise from the front left corner, one side at a time
+        let corners = [
+            (Vec2f { x: -half + inset, y: inset }, Vec2f { x: 1., y: 0. }),
+            (Vec2f { x: half - inset, y: inset }, Vec2f { x: 0., y: 1. }),
+            (Vec2f { x: half - inset, y: frontage - inset }, Vec2f { x: -1., y: 0. }),
+            (Vec2f { x: -half + inset, y: frontage - inset }, Vec2f { x: 0., y: -1. }),
+        ];
+
+        'layer: for (start, dir) in corners {
+            for k in 0..per_side {
+                if slots.len() == num {
+                    break 'layer;
+                }
+                slots.push(start + dir * (k as f64 * step));
+            }
+        }
+        layer += 1;
+    }
+
+    slots
+}
+
+pub fn orb_formation(num: usize, frontage: f64, spacing: f64) -> Vec<Vec2f> {
+    let mut slots = Vec::with_capacity(num);
+    //the rings have to hold everyone, widen the orb if they can't
+    let outer = (frontage / 2.).max(spacing * ((num as f64 / PI).sqrt() + 1.));
+    let mut radius = outer;
+
+    while slots.len() < num {
+        let ring = ((2. * PI * radius / spacing).floor() as usize).min(num - slots.len());
+
+        if radius < spacing / 2. || ring < 2 {
+            while slots.len() < num {
+                slots.push(Vec2f { x: 0., y: outer });
+            }
+            break;
+        }
+
+        for k in 0..ring {
+            let a = 2. * PI * k as f64 / ring as f64;
+            //first slot of every ring faces straight ahead
+            slots.push(Vec2f {
+                x: radius * a.sin(),
+                y: outer - radius * a.cos(),
+            });
+        }
+        radius -= spacing;
+    }
+
+    slots
+}
+
+pub fn skirmish_formation(num: usize, frontage: f64, spacing: f64) -> Vec<Vec2f> {
+    let width = files(frontage, spacing);
+    let half = (width - 1) as f64 / 2.;
+
+    (0..num)
+        .map(|i| {
+            let rank = i / width;
+            let stagger = if rank % 2 == 1 { 0.5 } else { 0. };
+            //fixed per index so a previewed order comes out the same
+            let jitter = ((i as f64 * 0.618_034).fract() - 0.5) * spacing * 0.25;
+
+            Vec2f {
+                x: ((i % width) as f64 - half + stagger) * spacing + jitter,
+                y: rank as f64 * spacing - jitter,
+            }
+        })
+        .collect()
+}
+
+///step > 0 holds the left flank forward, step < 0 the right
+pub fn echelon_formation(num: usize, frontage: f64, spacing: f64, step: f64) -> Vec<Vec2f> {
+    let width = files(frontage, spacing);
+    let half = (width - 1) as f64 / 2.;
+
+    (0..num)
+        .map(|i| {
+            let file = (i % width) as f64;
+            let setback = if step > 0. { file * step } else { (width - 1) as f64 * -step - file * -step };
+
+            Vec2f {
+                x: (file - half) * spacing,
+                y: (i / width) as f64 * spacing + setback,
+            }
+        })
+        .collect()
+}
+
+const SWAP_PASSES: usize = 4;
+
+///Reorders slots so that slots[i] belongs to boid i, keeping total travel short.
+///Greedy nearest pairs first, then pairwise swaps that shorten the sum, which also
+///removes crossing paths. Quadratic, fine for BASE_UNIT_CAPACITY boids
+pub fn assign_slots(boids: &[Vec2f], slots: &mut [Vec2f]) {
+    let n = boids.len().min(slots.len());
+    if n < 2 {
+        return;
+    }
+
+    let mut pairs: Vec<(f64, usize, usize)> = Vec::with_capacity(n * n);
+    for (i, b) in boids[..n].iter().enumerate() {
+        for (j, s) in slots[..n].iter().enumerate() {
+            pairs.push(((*b - *s).len(), i, j));
+        }
+    }
+    pairs.sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut boid_slot: Vec<Option<usize>> = vec![None; n];
+    let mut taken = vec![false; n];
+    for (_, i, j) in pairs {
+        if boid_slot[i].is_none() && !taken[j] {
+            boid_slot[i] = Some(j);
+            taken[j] = true;
+        }
+    }
+
+    let mut assigned: Vec<Vec2f> = boid_slot.iter().map(|j| slots[j.unwrap()]).collect();
+
+    for _ in 0..SWAP_PASSES {
+        let mut improved = false;
+        for i in 0..n {
+            for k in i + 1..n {
+                let cur = (boids[i] - assigned[i]).len() + (boids[k] - assigned[k]).len();
+                let swapped = (boids[i] - assigned[k]).len() + (boids[k] - assigned[i]).len();
+                if swapped + 1e-9 < cur {
+                    assigned.swap(i, k);
+                    improved = true;
+                }
+            }
+        }
+        if !improved {
+            break;
+        }
+    }
+
+    slots[..n].copy_from_slice(&assigned);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::formations::{assign_slots, Formation};
+    use crate::ops::Vec2f;
+    use crate::world::SimRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn formation_round_trips() {
+        let formations = [
+            Formation::Scatter,
+            Formation::phalanx(7),
+            Formation::Phalanx { width: 3, spacing: 30., rank_depth: 40. },
+            Formation::Idle { radius: 100. },
+        ];
+
+        for f in formations {
+            let text = ron::to_string(&f).unwrap();
+            assert_eq!(ron::from_str::<Formation>(&text).unwrap(), f);
+        }
+    }
+
+    #[test]
+    fn zero_width_from_a_file_is_one_file() {
+        let f = ron::from_str::<Formation>("Phalanx(width: 0, spacing: 20., rank_depth: 20.)").unwrap();
+        assert_eq!(f.width(), 1);
+        assert_eq!(f.depth(3), 2. * f.spacing());
+    }
+
+    #[test]
+    fn library_formations_have_distinct_slots() {
+        let mut rng = SimRng::seed_from_u64(0);
+        let formations = [
+            Formation::phalanx(8),
+            Formation::wedge(9),
+            Formation::hollow_square(8),
+            Formation::orb(10),
+            Formation::testudo(6),
+            Formation::skirmish(8),
+            Formation::echelon_left(8),
+            Formation::echelon_right(8),
+        ];
+
+        for f in formations {
+            for num in [1, 7, 64, 256] {
+                let slots = f.offsets(num, &mut rng);
+                assert_eq!(slots.len(), num, "{:?}", f);
+
+                for (i, a) in slots.iter().enumerate() {
+                    for b in slots[i + 1..].iter() {
+                        assert!((*a - *b).len() > 0.5 * f.spacing(), "{:?} {:?} {:?}", f, a, b);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn about_turn_keeps_boids_in_place() {
+        let boids: Vec<Vec2f> = (0..8).map(|i| Vec2f::from((i * 24, 0))).collect();
+        let mut slots: Vec<Vec2f> = boids.iter().rev().copied().collect();
+
+        assign_slots(&boids, &mut slots);
+
+        assert_eq!(slots, boids);
     }
 }
@@ -0,0 +1,94 @@
+use crate::ops::Vec2f;
+use std::collections::HashMap;
+
+///Uniform spatial hash. Entries are bucketed by the cell their position falls into,
+///a query only looks at the cells overlapping its area
+pub struct SpatialGrid<T> {
+    cell: f64,
+    cells: HashMap<(i32, i32), Vec<(T, Vec2f)>>,
+}
+
+impl<T: Copy> SpatialGrid<T> {
+    pub fn new(cell: f64) -> Self {
+        SpatialGrid {
+            cell,
+            cells: HashMap::new(),
+        }
+    }
+
+    fn key(&self, p: Vec2f) -> (i32, i32) {
+        ((p.x / self.cell).floor() as i32, (p.y / self.cell).floor() as i32)
+    }
+
+    ///Keeps the buckets allocated, the grid is refilled every tick
+    pub fn clear(&mut self) {
+        for bucket in self.cells.values_mut() {
+            bucket.clear();
+        }
+    }
+
+    pub fn insert(&mut self, item: T, pos: Vec2f) {
+        let key = self.key(pos);
+        self.cells.entry(key).or_insert_with(Vec::new).push((item, pos));
+    }
+
+    ///Entries inside the rectangle spanned by two opposite corners
+    pub fn query_rect(&self, p0: Vec2f, p1: Vec2f) -> impl Iterator<Item = (T, Vec2f)> + '_ {
+        let lo = Vec2f { x: p0.x.min(p1.x), y: p0.y.min(p1.y) };
+        let hi = Vec2f { x: p0.x.max(p1.x), y: p0.y.max(p1.y) };
+        let (k0, k1) = (self.key(lo), self.key(hi));
+
+        (k0.0..=k1.0)
+            .flat_map(move |x| (k0.1..=k1.1).map(move |y| (x, y)))
+            .filter_map(move |k| self.cells.get(&k))
+            .flat_map(|bucket| bucket.iter().copied())
+            .filter(move |(_, p)| p.x >= lo.x && p.x <= hi.x && p.y >= lo.y && p.y <= hi.y)
+    }
+
+    pub fn query_radius(&self, pos: Vec2f, r: f64) -> impl Iterator<Item = (T, Vec2f)> + '_ {
+        let corner = Vec2f { x: r, y: r };
+
+        self.query_rect(pos - corner, pos + corner)
+            .filter(move |(_, p)| (*p - pos).len() <= r)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::grid::SpatialGrid;
+    use crate::ops::Vec2f;
+    use quickcheck::quickcheck;
+
+    #[test]
+    fn radius_query_crosses_cells() {
+        let mut grid = SpatialGrid::new(10.);
+        grid.insert(1, Vec2f { x: -1., y: -1. });
+        grid.insert(2, Vec2f { x: 1., y: 1. });
+        grid.insert(3, Vec2f { x: 30., y: 0. });
+
+        let mut found: Vec<i32> = grid.query_radius(Vec2f::default(), 5.).map(|(i, _)| i).collect();
+        found.sort();
+        assert_eq!(found, vec![1, 2]);
+
+        grid.clear();
+        assert_eq!(grid.query_radius(Vec2f::default(), 50.).count(), 0);
+    }
+
+    quickcheck! {
+        fn rect_query_matches_scan(points: Vec<(i16, i16)>, x0: i16, y0: i16, x1: i16, y1: i16) -> bool {
+            let mut grid = SpatialGrid::new(16.);
+            for (i, p) in points.iter().enumerate() {
+                grid.insert(i, Vec2f::from((p.0 as i32, p.1 as i32)));
+            }
+
+            let (lx, hx) = (x0.min(x1), x0.max(x1));
+            let (ly, hy) = (y0.min(y1), y0.max(y1));
+            let expected = points
+                .iter()
+                .filter(|p| p.0 >= lx && p.0 <= hx && p.1 >= ly && p.1 <= hy)
+                .count();
+
+            grid.query_rect(Vec2f::from((x0 as i32, y0 as i32)), Vec2f::from((x1 as i32, y1 as i32))).count() == expected
+        }
+    }
+}
@@ -1,31 +1,130 @@
-use crate::units::{CompositeUnit, BasicUnit};
-use std::cmp::max;
+use crate::boids::Condition;
+use crate::grid::SpatialGrid;
+use crate::units::{CompositeUnit, BasicUnit, Unit};
+use crate::world::WorldId;
 
 pub trait Interactable {
-    fn manage_interaction(&mut self, other: &mut BasicUnit);
+    fn manage_interaction(&mut self, other: &mut BasicUnit, index: &SpatialGrid<WorldId>);
 }
 
 //has to be this way cos a company may be shielded by another company
 impl Interactable for BasicUnit {
-    fn manage_interaction(&mut self, other: &mut BasicUnit) {
-        if (self.center - other.center).len() > max(
+    fn manage_interaction(&mut self, other: &mut BasicUnit, index: &SpatialGrid<WorldId>) {
+        //friends in reach of each other are no engagement
+        if self.side == other.side {
+            return;
+        }
+        if (self.center - other.center).len() > f64::max(
                 self.select_radius + other.interaction_radius,
                 other.select_radius + self.interaction_radius,
             )
         { return; }
 
+        self.engaged = true;
+        other.engaged = true;
+
+        if in_contact(self, other, index) {
+            self.in_melee = true;
+            other.in_melee = true;
+        }
+
         //collide(self, other);
 
 
     }
 }
 
+///Some living soldier of a is within sword reach of a living soldier of b, looked up in the boid index
+fn in_contact(a: &BasicUnit, b: &BasicUnit, index: &SpatialGrid<WorldId>) -> bool {
+    let reach = a.troop_desc.melee_reach_standoff_max.max(b.troop_desc.melee_reach_standoff_max) as f64;
+    if (a.center - b.center).len() > a.select_radius + b.select_radius + reach {
+        return false;
+    }
+
+    let (ta, tb) = match (&a.troops, &b.troops) {
+        (Some(ta), Some(tb)) => (ta, tb),
+        _ => return false,
+    };
+    let alive_in_b = |id: WorldId| b.owns(id) && tb.condition[id - b.id - 1] != Condition::Dead;
+
+    ta.living().any(|p| index.query_radius(p, reach).any(|(id, _)| alive_in_b(id)))
+}
+
 impl Interactable for CompositeUnit {
-    fn manage_interaction(&mut self, other: &mut BasicUnit) {
-        if (self.center - other.center).len() > max(
+    fn manage_interaction(&mut self, other: &mut BasicUnit, _index: &SpatialGrid<WorldId>) {
+        if (self.center - other.center).len() > f64::max(
             self.select_radius + other.interaction_radius,
             other.select_radius + self.interaction_radius,
         )
         { return; }
     }
 }
+
+impl Unit {
+    ///Interactions are resolved company against company
+    pub fn interact(&mut self, other: &mut Unit, index: &SpatialGrid<WorldId>) {
+        for company in self.companies_mut() {
+            for other_company in other.companies_mut() {
+                company.manage_interaction(other_company, index);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::grid::SpatialGrid;
+    use crate::interaction::Interactable;
+    use crate::ops::Vec2f;
+    use crate::units::BasicUnit;
+    use crate::world::{SimRng, WorldId};
+    use rand::SeedableRng;
+
+    ///Four men in a file along y at x
+    fn company(x: f64, rng: &mut SimRng) -> BasicUnit {
+        let mut company = BasicUnit::new(Vec2f { x, y: 0. }, 4, rng);
+        for (i, pos) in company.troops.as_mut().unwrap().pos.iter_mut().enumerate() {
+            *pos = Vec2f { x, y: i as f64 * 10. };
+        }
+        company.center = Vec2f { x, y: 15. };
+        company.select_radius = 15.;
+        company
+    }
+
+    ///Boid index over the given companies, as the world builds it each tick
+    fn index(companies: &[&BasicUnit]) -> SpatialGrid<WorldId> {
+        let mut index = SpatialGrid::new(32.);
+        for company in companies {
+            for (j, pos) in company.troops.as_ref().unwrap().pos.iter().enumerate() {
+                index.insert(company.id + j + 1, *pos);
+            }
+        }
+        index
+    }
+
+    #[test]
+    fn only_enemies_engage_and_fight_within_reach() {
+        let mut rng = SimRng::seed_from_u64(0);
+        let mut a = company(0., &mut rng);
+        let mut b = company(30., &mut rng);
+        b.id = a.id + a.num_boids() + 1;
+        let mut archers = company(300., &mut rng);
+        archers.id = b.id + b.num_boids() + 1;
+        let grid = index(&[&a, &b, &archers]);
+
+        a.manage_interaction(&mut b, &grid);
+        assert!(!a.engaged && !b.engaged);
+        assert!(!a.in_melee && !b.in_melee);
+
+        b.side = 1;
+        a.manage_interaction(&mut b, &grid);
+        assert!(a.engaged && b.engaged);
+        assert!(a.in_melee && b.in_melee);
+
+        archers.side = 1;
+        archers.interaction_radius = 1200.;
+        a.in_melee = false;
+        a.manage_interaction(&mut archers, &grid);
+        assert!(a.engaged && !a.in_melee && !archers.in_melee);
+    }
+}
@@ -1,16 +1,17 @@
-#![feature(derive_default_enum)]
-#![feature(drain_filter)]
-#![feature(hash_drain_filter)]
-#![feature(slice_group_by)]
-
 pub mod app;
 pub mod boids;
+pub mod command;
 pub mod container;
+pub mod formations;
+pub mod grid;
 pub mod ops;
+pub mod path;
 pub mod player;
 pub mod world;
 pub mod drawable;
+pub mod drill;
 pub mod traits;
+pub mod troops;
 pub mod units;
 pub mod interaction;
 
@@ -1,18 +1,21 @@
-#![feature(derive_default_enum)]
-#![feature(drain_filter)]
-#![feature(hash_drain_filter)]
-
 mod app;
 mod boids;
+mod command;
 mod container;
 mod ops;
+mod path;
 mod player;
 mod world;
 pub mod units;
 mod drawable;
+mod drill;
 mod formations;
+mod grid;
 mod traits;
+mod troops;
+mod interaction;
 
+use std::env;
 use std::ops::AddAssign;
 use crate::app::App;
 use glutin_window::GlutinWindow as Window;
@@ -38,8 +41,16 @@ fn main() {
         .build()
         .unwrap();
 
+    // Pass --seed N to replay a run exactly.
+    let seed = env::args()
+        .skip_while(|a| a != "--seed")
+        .nth(1)
+        .and_then(|s| s.parse().ok())
+        .unwrap_or_else(rand::random);
+    println!("seed {}", seed);
+
     // Create a new game and run it.
-    let mut app = App::new(opengl);
+    let mut app = App::new(opengl, seed);
 
     let mut events = Events::new(EventSettings::new());
     while let Some(e) = events.next(&mut window) {
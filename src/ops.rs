@@ -95,6 +95,29 @@ impl Vec2f {
         self
     }
 
+    pub fn dot(self, other: Vec2f) -> f64 {
+        self.x * other.x + self.y * other.y
+    }
+
+    ///z of the 3d cross product, sign tells which side other is on
+    pub fn cross(self, other: Vec2f) -> f64 {
+        self.x * other.y - self.y * other.x
+    }
+
+    ///Signed angle that rotates self onto other, in (-pi; pi]
+    pub fn angle_to(self, other: Vec2f) -> f64 {
+        f64::atan2(self.cross(other), self.dot(other))
+    }
+
+    pub fn rotate(self, angle: f64) -> Vec2f {
+        let (sin, cos) = angle.sin_cos();
+
+        Vec2f {
+            x: self.x * cos - self.y * sin,
+            y: self.x * sin + self.y * cos,
+        }
+    }
+
     pub fn rot_align(self, other: Vec2f) -> Vec2f {
         //find rotation from (1; 0) to (other)
 
@@ -159,6 +182,20 @@ mod tests {
             true
         }
 
+        fn rotate_by_angle_to_aligns(x1: f64, y1: f64, x2: f64, y2: f64) -> bool {
+            if !(x1 + x2 + y1 + y2).is_normal() || x1.abs() + y1.abs() > 1e100 || x2.abs() + y2.abs() > 1e100 {
+                  return true
+            }
+
+            let a = Vec2f { x: x1, y: y1 }.normalise();
+            let b = Vec2f { x: x2, y: y2 }.normalise();
+            if a.len() < 0.5 || b.len() < 0.5 {
+                  return true
+            }
+
+            (a.rotate(a.angle_to(b)) - b).len() < 1e-9
+        }
+
         fn add_correct(x1: f64, y1: f64, x2: f64, y2: f64) -> bool {
               if !(x1 + x2 + y1 + y2).is_normal() {
                   return true
@@ -0,0 +1,185 @@
+use crate::ops::Vec2f;
+use serde::{Deserialize, Serialize};
+
+const SAMPLES_PER_SEGMENT: usize = 16;
+///half the distance over which curvature is measured
+const CURVE_PROBE: f64 = 12.;
+
+///Catmull-Rom spline through waypoints, sampled densely and walked by arc length.
+///Only the waypoints are serialized
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "Vec<Vec2f>", into = "Vec<Vec2f>")]
+pub struct Path {
+    points: Vec<Vec2f>,
+    samples: Vec<Vec2f>,
+    ///arc length from the start to each sample
+    dist: Vec<f64>,
+}
+
+impl From<Vec<Vec2f>> for Path {
+    fn from(points: Vec<Vec2f>) -> Self {
+        Path::new(points)
+    }
+}
+
+impl From<Path> for Vec<Vec2f> {
+    fn from(path: Path) -> Self {
+        path.points
+    }
+}
+
+impl Path {
+    pub fn new(points: Vec<Vec2f>) -> Self {
+        let mut path = Path {
+            points,
+            samples: vec![],
+            dist: vec![],
+        };
+        path.resample();
+        path
+    }
+
+    pub fn points(&self) -> &[Vec2f] {
+        &self.points
+    }
+
+    pub fn samples(&self) -> &[Vec2f] {
+        &self.samples
+    }
+
+    pub fn push(&mut self, p: Vec2f) {
+        self.points.push(p);
+        self.resample();
+    }
+
+    pub fn set_start(&mut self, p: Vec2f) {
+        if let Some(first) = self.points.first_mut() {
+            *first = p;
+            self.resample();
+        }
+    }
+
+    pub fn set_end(&mut self, p: Vec2f) {
+        if let Some(last) = self.points.last_mut() {
+            *last = p;
+            self.resample();
+        }
+    }
+
+    pub fn end(&self) -> Vec2f {
+        *self.points.last().unwrap_or(&Vec2f::default())
+    }
+
+    pub fn length(&self) -> f64 {
+        *self.dist.last().unwrap_or(&0.)
+    }
+
+    fn resample(&mut self) {
+        self.samples.clear();
+        self.dist.clear();
+
+        let n = self.points.len();
+        if n == 0 {
+            return;
+        }
+
+        self.samples.push(self.points[0]);
+        for i in 0..n - 1 {
+            //ends are doubled so the curve starts and stops on them
+            let p0 = self.points[i.saturating_sub(1)];
+            let p1 = self.points[i];
+            let p2 = self.points[i + 1];
+            let p3 = self.points[(i + 2).min(n - 1)];
+
+            for k in 1..=SAMPLES_PER_SEGMENT {
+                let t = k as f64 / SAMPLES_PER_SEGMENT as f64;
+                self.samples.push(catmull_rom(p0, p1, p2, p3, t));
+            }
+        }
+
+        let mut total = 0.;
+        self.dist.push(0.);
+        for (a, b) in self.samples.iter().zip(self.samples.iter().skip(1)) {
+            total += (*b - *a).len();
+            self.dist.push(total);
+        }
+    }
+
+    ///Index of the sample segment containing arc length s
+    fn segment(&self, s: f64) -> usize {
+        let k = self.dist.partition_point(|d| *d <= s);
+        k.saturating_sub(1).min(self.samples.len().saturating_sub(2))
+    }
+
+    pub fn point_at(&self, s: f64) -> Vec2f {
+        if self.samples.len() < 2 {
+            return self.end();
+        }
+
+        let s = s.clamp(0., self.length());
+        let k = self.segment(s);
+        let span = self.dist[k + 1] - self.dist[k];
+        let t = if span > 0. { (s - self.dist[k]) / span } else { 0. };
+
+        self.samples[k] + (self.samples[k + 1] - self.samples[k]) * t
+    }
+
+    pub fn tangent_at(&self, s: f64) -> Vec2f {
+        if self.samples.len() < 2 {
+            return Vec2f::default();
+        }
+
+        let mut k = self.segment(s.clamp(0., self.length()));
+        //zero length pieces come from repeated waypoints
+        while k + 2 < self.samples.len() && (self.samples[k + 1] - self.samples[k]).len() == 0. {
+            k += 1;
+        }
+        (self.samples[k + 1] - self.samples[k]).normalise()
+    }
+
+    ///Turn per unit of length around s, 1 / radius of the curve
+    pub fn curvature_at(&self, s: f64) -> f64 {
+        let t0 = self.tangent_at(s - CURVE_PROBE);
+        let t1 = self.tangent_at(s + CURVE_PROBE);
+
+        t0.angle_to(t1).abs() / (2. * CURVE_PROBE)
+    }
+}
+
+pub fn catmull_rom(p0: Vec2f, p1: Vec2f, p2: Vec2f, p3: Vec2f, t: f64) -> Vec2f {
+    let t2 = t * t;
+    let t3 = t2 * t;
+
+    (p1 * 2.
+        + (p2 - p0) * t
+        + (p0 * 2. - p1 * 5. + p2 * 4. - p3) * t2
+        + (p1 * 3. - p0 - p2 * 3. + p3) * t3)
+        * 0.5
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ops::Vec2f;
+    use crate::path::Path;
+
+    #[test]
+    fn straight_path() {
+        let path = Path::new(vec![Vec2f::from((0, 0)), Vec2f::from((100, 0)), Vec2f::from((200, 0))]);
+
+        assert!((path.length() - 200.).abs() < 1e-6);
+        assert!((path.point_at(50.) - Vec2f::from((50, 0))).len() < 1e-6);
+        assert_eq!(path.tangent_at(120.), Vec2f::from((1, 0)));
+        assert!(path.curvature_at(100.) < 1e-9);
+    }
+
+    #[test]
+    fn curve_passes_waypoints() {
+        let points = vec![Vec2f::from((0, 0)), Vec2f::from((100, 0)), Vec2f::from((100, 100))];
+        let path = Path::new(points.clone());
+
+        for p in points {
+            assert!(path.samples().iter().any(|s| (*s - p).len() < 1e-9));
+        }
+        assert!(path.curvature_at(path.length() / 2.) > 0.);
+    }
+}
@@ -1,9 +1,9 @@
-use crate::app::CLICK_PRECISION;
 //use crate::container::{get_boid_container, is_boid_of_container, is_container};
 use crate::ops::Vec2f;
-use crate::player::PlayerAction::{AddFormUp, AddMove, FormUp, Move};
-use crate::world::{World, WorldId};
+use crate::player::PlayerAction::{AddColumn, AddFormUp, AddMove, Column, FormUp, Move};
+use crate::world::{World, WorldId, CLICK_PRECISION};
 use std::collections::HashSet;
+use petgraph::stable_graph::NodeIndex;
 
 #[derive(Default)]
 pub struct PlayerState {
@@ -16,6 +16,12 @@ pub struct PlayerState {
 
     pub ctrl_pressed: bool,
     pub shift_pressed: bool,
+    ///orders march in column
+    pub column_pressed: bool,
+    ///right drag sets the facing of a move instead of a front
+    pub alt_pressed: bool,
+    ///orders loop until replaced by one given without it
+    pub patrol_pressed: bool,
 
     pub zoom: f32,
     pub to_zoom: f32, //Amount left to animate zooming in/out
@@ -30,8 +36,14 @@ pub struct PlayerState {
     pub r_click: bool,
 
     pub selected: HashSet<WorldId>,
+    ///command node whose subtree is selected, orders go down the chain from it
+    pub command: Option<NodeIndex>,
+    ///(company id, goal index, where the marker was left) of the waypoint marker held by the left button
+    pub dragged_goal: Option<(WorldId, usize, Vec2f)>,
 
     pub action: PlayerAction,
+    ///patrol modifier as it was when the action was given
+    pub patrol: bool,
 }
 
 #[derive(Default, Copy, Clone)]
@@ -40,6 +52,9 @@ pub enum PlayerAction {
     None,
     Move(Vec2f, Option<Vec2f>),
     AddMove(Vec2f, Option<Vec2f>),
+    ///target, facing to deploy into line on arrival
+    Column(Vec2f, Option<Vec2f>),
+    AddColumn(Vec2f, Option<Vec2f>),
     FormUp(Vec2f, Vec2f),
     AddFormUp(Vec2f, Vec2f),
 }
@@ -51,6 +66,7 @@ impl PlayerState {
         }
 
         if self.l_click {
+            self.command = None;
             if (self.l2 - self.l1).man() < CLICK_PRECISION {
                 println!("LCLICK");
                 let mut ids = world.get_ids_at(self.l2);
@@ -126,6 +142,19 @@ impl PlayerState {
                     self.selected.insert(id);
                 }
             };
+        } else if self.r_click && self.column_pressed {
+            //drag deploys into line facing the drag direction
+            let deploy = if (self.r2 - self.r1).man() < CLICK_PRECISION {
+                None
+            } else {
+                Some(self.r2 - self.r1)
+            };
+
+            if self.shift_pressed {
+                self.action = AddColumn(self.r1, deploy)
+            } else {
+                self.action = Column(self.r1, deploy)
+            }
         } else if self.r_click {
             if (self.r2 - self.r1).man() < CLICK_PRECISION {
                 println!("RCLICK");
@@ -134,6 +163,14 @@ impl PlayerState {
                 } else {
                     self.action = Move(self.r2, None) //RMB click
                 }
+            } else if self.alt_pressed {
+                //move to where the drag started, facing along the drag
+                let dir = Some(self.r2 - self.r1);
+                if self.shift_pressed {
+                    self.action = AddMove(self.r1, dir)
+                } else {
+                    self.action = Move(self.r1, dir)
+                }
             } else if self.shift_pressed {
                 println!("RDRAG + SHIFT");
                 self.action = AddFormUp(self.r2, self.r1) //RMB drag
@@ -144,5 +181,9 @@ impl PlayerState {
         } else {
             self.action = PlayerAction::None
         }
+
+        if self.r_click {
+            self.patrol = self.patrol_pressed;
+        }
     }
 }
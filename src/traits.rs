@@ -1,5 +1,4 @@
-use std::collections::VecDeque;
-use crate::units::Goal;
+use crate::units::{front_facing, Goal};
 use crate::ops::Vec2f;
 use crate::player::PlayerAction;
 use crate::world::WorldId;
@@ -26,15 +25,7 @@ pub trait Clickable {
         (p - self.center).len() < self.radius
     }*/
 
-    //todo: bench normal loop; should be zero-cost
-    /*pub fn get_boid_at(&self, c: Vec2f) -> Option<WorldId> {
-        for (i, p) in self.ent.pos.iter().enumerate() {
-            if (*p - c).man() < CLICK_PRECISION {
-                return Some(self.id + i + 1);
-            }
-        }
-        None
-    }*/
+    //boid picking goes through World::boid_index, see World::get_ids_at
 }
 
 pub trait Selectable {
@@ -43,33 +34,44 @@ pub trait Selectable {
 }
 
 pub trait Controllable {
-    fn new_order(&self, goal: Goal);
-    fn add_order(&self, goal: Goal);
-    ///maybe better to provide a getter?
-    fn assign_goals(&self, mut goals: &VecDeque<Goal>, action: PlayerAction, default_dir, center: Vec2f) {
+    ///Replaces all queued goals
+    fn new_order(&mut self, goal: Goal);
+    ///Queues a goal after the current ones
+    fn add_order(&mut self, goal: Goal);
+
+    ///Moves without a facing end facing the way they went, default_dir if they go nowhere
+    fn assign_goals(&mut self, action: PlayerAction, default_dir: Vec2f, center: Vec2f) {
+        let facing = |pos: Vec2f, dir: Option<Vec2f>| {
+            dir.unwrap_or_else(|| {
+                let heading = pos - center;
+                if heading.len() > 0. { heading } else { default_dir }
+            })
+        };
+
         match action {
             PlayerAction::None => {}
             PlayerAction::Move(pos, dir) => {
-                goals.clear();
-                goals.push_back(Goal::Move(pos, dir.unwrap_or(default_dir)))
+                self.new_order(Goal::Move(pos, facing(pos, dir)))
             }
             PlayerAction::AddMove(pos, dir) => {
-                goals.push_back(Goal::Move(pos, dir.unwrap_or(default_dir)))
+                self.add_order(Goal::Move(pos, facing(pos, dir)))
+            }
+            PlayerAction::Column(pos, deploy) => {
+                self.new_order(Goal::Column(pos, deploy))
+            }
+            PlayerAction::AddColumn(pos, deploy) => {
+                self.add_order(Goal::Column(pos, deploy))
             }
             PlayerAction::FormUp(pos1, pos2) => {
-                let center_front = (pos2 - pos1) * 0.5;
-                let dir = center_front - center;
+                let dir = front_facing(pos2, pos1, center);
 
-                goals.clear();
-                goals.push_back(Goal::Front(pos2, pos1, dir))
+                self.new_order(Goal::Front(pos2, pos1, dir))
             }
             PlayerAction::AddFormUp(pos1, pos2) => {
-                let center_front = (pos2 - pos1) * 0.5;
-                let dir = center_front - center;
+                let dir = front_facing(pos2, pos1, center);
 
-                goals.push_back(Goal::Front(pos2, pos1, dir))
+                self.add_order(Goal::Front(pos2, pos1, dir))
             }
         }
     }
 }
-
@@ -0,0 +1,184 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+///Catalog read at startup when it exists, the built in copy is used otherwise
+pub const CATALOG_PATH: &str = "data/troops.ron";
+const BUILTIN_CATALOG: &str = include_str!("../data/troops.ron");
+
+///foot soldiers take half a second to reach full speed
+const SPEED_UP_TIME: f64 = 0.5;
+const MOUNTED_SPEED_UP_TIME: f64 = 1.;
+
+///What a troop type can do. Fields missing from a catalog entry take the generic infantry values
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TroopDesc {
+    pub name: String,
+    pub mass: f32,
+    pub base_spd: f32,
+    pub charge_spd: f32,
+
+    pub mounted: bool,
+    pub ranged: bool,
+    pub ranged_ammo: u8,
+    ///arrow/pilum reach on level plane
+    pub ranged_base_reach: f32,
+
+    ///reach of dagger
+    pub melee_reach_cqb: f32,
+    ///min usable reach of spear/sword
+    pub melee_reach_standoff_min: f32,
+    ///max usable reach of spear/sword
+    pub melee_reach_standoff_max: f32,
+    pub block_chance: f32,
+
+    ///limits on ranks when the frontage is dragged out, 0 for none
+    pub min_ranks: u8,
+    pub max_ranks: u8,
+
+    ///stamina spent per second running at charge_spd, falls off with the square of the pace
+    pub speed_drain: f32,
+    ///stamina spent per second in contact with the enemy
+    pub fight_drain: f32,
+    ///stamina spent per second at charge_spd up a 1 in 1 slope
+    pub climb_drain: f32,
+    ///stamina regained per second under a Hold or Idle order
+    pub stamina_recovery: f32,
+}
+
+impl Default for TroopDesc {
+    fn default() -> Self {
+        TroopDesc {
+            name: String::new(),
+            mass: 80.,
+            base_spd: 100.,
+            charge_spd: 150.,
+            mounted: false,
+            ranged: false,
+            ranged_ammo: 0,
+            ranged_base_reach: 0.,
+            melee_reach_cqb: 20.,
+            melee_reach_standoff_min: 24.,
+            melee_reach_standoff_max: 40.,
+            block_chance: 0.3,
+            min_ranks: 0,
+            max_ranks: 0,
+            speed_drain: 0.02,
+            fight_drain: 0.01,
+            climb_drain: 0.05,
+            stamina_recovery: 0.01,
+        }
+    }
+}
+
+impl TroopDesc {
+    pub fn max_speed(&self) -> f64 {
+        self.base_spd as f64
+    }
+
+    pub fn max_acc(&self) -> f64 {
+        let time = if self.mounted { MOUNTED_SPEED_UP_TIME } else { SPEED_UP_TIME };
+        self.max_speed() / time
+    }
+
+    pub fn charge_speed(&self) -> f64 {
+        self.charge_spd as f64
+    }
+
+    ///Stamina spent per second moving at speed up grade (rise over run, downhill is free)
+    pub fn exertion(&self, speed: f64, fighting: bool, grade: f64) -> f32 {
+        let pace = (speed / self.charge_speed().max(1.)) as f32;
+        let mut drain = self.speed_drain * pace * pace + self.climb_drain * grade.max(0.) as f32 * pace;
+        if fighting {
+            drain += self.fight_drain;
+        }
+        drain
+    }
+
+    ///How far from its ranks the company can hurt anyone
+    pub fn reach(&self) -> f64 {
+        let ranged = if self.ranged && self.ranged_ammo > 0 { self.ranged_base_reach } else { 0. };
+        self.melee_reach_standoff_max.max(ranged) as f64
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TroopCatalog {
+    troops: HashMap<String, TroopDesc>,
+}
+
+impl TroopCatalog {
+    pub fn parse(text: &str) -> Result<TroopCatalog, Box<dyn Error>> {
+        let list: Vec<TroopDesc> = ron::from_str(text)?;
+        Ok(TroopCatalog {
+            troops: list.into_iter().map(|t| (t.name.clone(), t)).collect(),
+        })
+    }
+
+    pub fn load(path: &str) -> Result<TroopCatalog, Box<dyn Error>> {
+        TroopCatalog::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn builtin() -> TroopCatalog {
+        TroopCatalog::parse(BUILTIN_CATALOG).expect("built in troop catalog is malformed")
+    }
+
+    ///The catalog at CATALOG_PATH, or the built in one when there is no such file
+    pub fn startup() -> Result<TroopCatalog, Box<dyn Error>> {
+        if Path::new(CATALOG_PATH).exists() {
+            TroopCatalog::load(CATALOG_PATH)
+        } else {
+            Ok(TroopCatalog::builtin())
+        }
+    }
+
+    ///An empty name is generic infantry
+    pub fn get(&self, name: &str) -> Option<TroopDesc> {
+        if name.is_empty() {
+            return Some(TroopDesc::default());
+        }
+        self.troops.get(name).cloned()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.troops.keys().map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::troops::{TroopCatalog, TroopDesc};
+
+    #[test]
+    fn builtin_catalog_has_the_legion() {
+        let catalog = TroopCatalog::builtin();
+
+        for name in ["hastati", "principes", "triarii", "velites", "equites", "sagittarii"] {
+            let desc = catalog.get(name).unwrap();
+            assert_eq!(desc.name, name);
+            assert!(desc.max_speed() > 0. && desc.reach() > 0.);
+        }
+        assert!(catalog.get("elephants").is_none());
+
+        let equites = catalog.get("equites").unwrap();
+        let triarii = catalog.get("triarii").unwrap();
+        assert!(equites.max_speed() > triarii.max_speed());
+        assert!(catalog.get("sagittarii").unwrap().reach() > triarii.reach());
+    }
+
+    #[test]
+    fn charging_and_fighting_tire_faster() {
+        let desc = TroopDesc::default();
+
+        let march = desc.exertion(desc.max_speed(), false, 0.);
+        let charge = desc.exertion(desc.charge_speed(), false, 0.);
+        assert!(desc.exertion(0., false, 0.) == 0.);
+        assert!(charge > march * 2.);
+        assert!(desc.exertion(desc.max_speed(), true, 0.) > march);
+        assert!(desc.exertion(desc.max_speed(), false, 0.2) > march);
+        assert_eq!(desc.exertion(desc.max_speed(), false, -0.2), march);
+    }
+}
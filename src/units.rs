@@ -1,54 +1,113 @@
 use std::collections::VecDeque;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use graphics::{Context, Graphics};
 use serde::{Deserialize, Serialize};
-use crate::container::Container;
+use crate::container::{Container, ContainerState};
 use crate::ops::Vec2f;
 use crate::traits::{Clickable, Controllable, Identifiable, Selectable};
 
 use rand::Rng;
-use crate::boids::BoidVec;
-use crate::drawable::Drawable;
+use crate::boids::{separation, BoidState, BoidVec, Condition};
 use crate::formations;
-use crate::formations::{FORMATION_SPACING, FormationFunction};
+use crate::formations::{assign_slots, FORMATION_SPACING, Formation};
 use crate::units::Goal::Idle;
-use crate::world::{Identifiable, WorldId};
+use crate::world::{SimRng, WorldId};
+use crate::grid::SpatialGrid;
+use crate::path::Path;
+use crate::drill::{Drill, DrillRun, DrillStatus};
+use crate::troops::TroopDesc;
+use std::slice;
 
-const ACC_MAX: f64 = 1000.;
-const VEL_MAX: f64 = 100.;
 const DIST_REPEL: f64 = 20.;
 const DIST_MARGIN: f64 = 1.;
-const COLUMN_WIDTH: i32 = 4;
+const SEPARATION_GAIN: f64 = 4.;
+///idle boids amble
+const IDLE_PACE: f64 = 0.3;
+///how far an idle slot wanders per second
+const IDLE_DRIFT: f64 = 6.;
+///idle crowd takes this much more room per boid than a formation
+const IDLE_LOOSENESS: f64 = 1.5;
+///exhausted soldiers move and strike at this much less than fresh ones
+const FATIGUE_PENALTY: f64 = 0.5;
+///wounded boids limp along at this fraction of their speed
+const WOUNDED_PACE: f64 = 0.5;
+///holding boids brace, pushing back to their slots harder and yielding less to neighbours
+const HOLD_BRACE: f64 = 2.;
+const HOLD_SEPARATION: f64 = 0.25;
+///outer file of a wheel walks at this fraction of full speed
+const WHEEL_PACE: f64 = 0.8;
+///smaller facing changes are left to the boids
+const WHEEL_MIN_ANGLE: f64 = 0.1;
+///shorter moves shuffle into place instead of marching off and turning
+const MARCH_MIN_DIST: f64 = 2. * FORMATION_SPACING;
+///on a curve the outer file walks further, the formation slows by this much per unit of curvature * half frontage
+const CURVE_SLOWDOWN: f64 = 1.;
+const COLUMN_WIDTH: usize = 4;
+///column head walks slower than full speed so the tail can keep up
+const COLUMN_PACE: f64 = 0.8;
+///Radius of a queued goal's marker on the map, also how close a click has to land to pick it
+pub const GOAL_MARKER_SIZE: f64 = 10.;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Goal {
     Idle(Vec2f),
     Hold,
     Move(Vec2f, Vec2f),
-    Column(Vec2f),
+    ///turn in place, wheeling up to a quarter turn, about-facing beyond
+    Face(Vec2f),
+    ///march along a curve through waypoints, facing on arrival
+    Path(Path, Vec2f),
+    ///target, facing to deploy into line on arrival
+    Column(Vec2f, Option<Vec2f>),
     Front(Vec2f, Vec2f, Vec2f),
 }
 
-#[derive(Default, Serialize, Deserialize)]
-struct TroopDesc {
-    name: String,
-    mass: f32,
-    base_spd: f32,
-    charge_spd: f32,
-
-    mounted: bool,
-    ranged: bool,
-    ranged_ammo: u8,
-    ///arrow/pilum reach on level plane
-    ranged_base_reach: f32,
-
-    ///reach of dagger
-    melee_reach_cqb: f32,
-    ///min usable reach of spear/sword
-    melee_reach_standoff_min: f32,
-    ///max usable reach of spear/sword
-    melee_reach_standoff_max: f32,
-    block_chance: f32,
+impl Goal {
+    ///Hold and Idle last until another order replaces them
+    pub fn is_standing(&self) -> bool {
+        matches!(self, Goal::Hold | Goal::Idle(_))
+    }
+
+    ///Where the unit ends up, None for goals that stay in place
+    pub fn target(&self) -> Option<Vec2f> {
+        match self {
+            Goal::Idle(pos) | Goal::Move(pos, _) | Goal::Column(pos, _) => Some(*pos),
+            Goal::Path(path, _) => Some(path.end()),
+            Goal::Front(p1, p2, _) => Some((*p1 + *p2) * 0.5),
+            Goal::Hold | Goal::Face(_) => None,
+        }
+    }
+
+    ///Which way the unit ends up facing, None if it keeps its current one
+    pub fn facing(&self) -> Option<Vec2f> {
+        match self {
+            Goal::Move(_, dir) | Goal::Path(_, dir) | Goal::Face(dir) | Goal::Front(_, _, dir) => Some(*dir),
+            Goal::Column(_, deploy) => *deploy,
+            Goal::Idle(_) | Goal::Hold => None,
+        }
+    }
+
+    ///Shifts the goal so that its target is pos. Fronts keep their width, paths only move their end
+    pub fn set_target(&mut self, pos: Vec2f) {
+        match self {
+            Goal::Idle(p) | Goal::Move(p, _) | Goal::Column(p, _) => *p = pos,
+            Goal::Path(path, _) => path.set_end(pos),
+            Goal::Front(p1, p2, _) => {
+                let shift = pos - (*p1 + *p2) * 0.5;
+                *p1 = *p1 + shift;
+                *p2 = *p2 + shift;
+            }
+            Goal::Hold | Goal::Face(_) => {}
+        }
+    }
+}
+
+///Formation turning as a rigid body around pivot until it faces facing
+#[derive(Debug, Copy, Clone)]
+pub struct Wheel {
+    pub pivot: Vec2f,
+    pub facing: Vec2f,
+    ///distance from the pivot to the outer file, sets the angular speed
+    pub outer: f64,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -57,6 +116,36 @@ pub enum Unit {
     CompositeUnit(CompositeUnit)
 }
 
+impl Unit {
+    pub fn companies(&self) -> &[BasicUnit] {
+        match self {
+            Unit::BasicUnit(b) => slice::from_ref(b),
+            Unit::CompositeUnit(c) => &c.troops,
+        }
+    }
+
+    pub fn companies_mut(&mut self) -> &mut [BasicUnit] {
+        match self {
+            Unit::BasicUnit(b) => slice::from_mut(b),
+            Unit::CompositeUnit(c) => &mut c.troops,
+        }
+    }
+
+    pub fn process_boids(&mut self, dt: f64, rng: &mut SimRng, index: &SpatialGrid<WorldId>) {
+        for company in self.companies_mut() {
+            company.p_b(dt, rng, index);
+            company.tire(dt);
+            company.update_condition();
+        }
+        if let Unit::CompositeUnit(battalion) = self {
+            battalion.advance_drill(dt);
+            battalion.update_center();
+            battalion.update_condition();
+            battalion.retire_goals();
+        }
+    }
+}
+
 impl Identifiable for Unit {
     fn generate_id(&self) -> WorldId {
         match self {
@@ -74,35 +163,79 @@ impl Identifiable for Unit {
 }
 
 #[derive(Serialize, Deserialize)]
-pub(crate) struct BasicUnit {
+pub struct BasicUnit {
+    pub id: WorldId,
     pub center: Vec2f,
     pub direction: Vec2f,
     pub select_radius: f64,
     pub interaction_radius: f64,
 
-    #[serde(skip)]
-    #[serde(default = "crate::formations::crutch")]
-    pub formation: FormationFunction,//remove this, change to enum maybe
+    pub formation: Formation,
     pub formation_positions: Vec<Vec2f>,
+    ///formation_positions have to be recalculated from the front goal
+    #[serde(default)]
+    pub reform: bool,
+    #[serde(skip)]
+    pub wheel: Option<Wheel>,
+    ///living boids the slots were handed out to, a death lays them out again
+    #[serde(skip)]
+    slotted: usize,
 
     ///.first is next goal
     pub goals: VecDeque<Goal>,
+    ///positions of the column head, newest first
+    #[serde(skip)]
+    pub trail: VecDeque<Vec2f>,
+    ///arc length covered along the front Path goal
+    #[serde(default)]
+    pub path_progress: f64,
+    ///formation offsets in slot order while following a path
+    #[serde(skip)]
+    path_slots: Vec<Vec2f>,
+    ///goals are looped through instead of consumed, for pickets and sentries
+    #[serde(default)]
+    pub patrol: bool,
+    ///ordered goals a patrol starts over with once the queue runs out
+    #[serde(default)]
+    pub route: Vec<Goal>,
 
     pub selected: bool,
+    ///army the company fights for, companies of any other side are the enemy
+    #[serde(default)]
+    pub side: u8,
+    ///an enemy unit is within reach of this one or this one of it this tick, set by manage_interaction
+    #[serde(skip)]
+    pub engaged: bool,
+    ///an enemy soldier is within sword reach of one of ours this tick, set by manage_interaction
+    #[serde(skip)]
+    pub in_melee: bool,
+    #[serde(skip)]
+    pub lod: ContainerState,
 
+    ///derived from the soldiers every step, see update_condition
     pub fatigue: f32,
     pub morale: f32,
     pub experience: f32,
     pub avg_age: f32,
 
+    ///name in the troop catalog, empty for generic infantry
+    #[serde(default)]
+    pub troop_type: String,
+    ///filled in from the catalog by troop_type, see World::equip_all
+    #[serde(skip)]
     pub troop_desc: TroopDesc,
     pub troops: Option<BoidVec>,
 }
 
 impl BasicUnit {
-    pub(crate) fn new(pos: Vec2f, num: usize) -> Self {
-        BasicUnit {
+    pub fn new(pos: Vec2f, num: usize, rng: &mut SimRng) -> Self {
+        let mut unit = BasicUnit {
+            id: 0,
             selected: false,
+            side: 0,
+            engaged: false,
+            in_melee: false,
+            lod: ContainerState::Hot,
             fatigue: 0.0,
             morale: 1.0,
             experience: 0.0,
@@ -111,56 +244,717 @@ impl BasicUnit {
             direction: Vec2f{x: 1.,y: 0.},
             select_radius: 0.0,
             goals: VecDeque::from([Idle(pos)]),
-            formation: formations::default_formation,
+            trail: VecDeque::new(),
+            path_progress: 0.,
+            path_slots: vec![],
+            patrol: false,
+            route: vec![],
+            formation: Formation::phalanx((num as f64).sqrt().ceil() as usize),
             formation_positions: Vec::with_capacity(num),
-            troops: None,
+            reform: true,
+            wheel: None,
+            slotted: 0,
+            troops: Some(BoidVec::random(pos, num, rng)),
             interaction_radius: 0.0,
+            troop_type: String::new(),
             troop_desc: TroopDesc::default(),
+        };
+        unit.id = unit.generate_id();
+        unit.equip(TroopDesc::default());
+        unit
+    }
+
+    ///A change of troop type issues its missiles, reloading a saved company keeps what is left
+    pub fn equip(&mut self, desc: TroopDesc) {
+        if desc.name != self.troop_type {
+            if let Some(troops) = &mut self.troops {
+                let ammo = if desc.ranged { desc.ranged_ammo } else { 0 };
+                for a in troops.ammo.iter_mut() {
+                    *a = ammo;
+                }
+            }
+        }
+
+        self.troop_type = desc.name.clone();
+        self.interaction_radius = desc.reach();
+        self.troop_desc = desc;
+    }
+
+    pub fn num_boids(&self) -> usize {
+        self.troops.as_ref().map_or(0, |t| t.len())
+    }
+
+    pub fn num_alive(&self) -> usize {
+        self.troops.as_ref().map_or(0, |t| t.num_alive())
+    }
+
+    ///Speed, fighting and climbing drain each soldier's stamina, standing orders let it come back.
+    ///There is no terrain yet, so every step is on the level
+    pub fn tire(&mut self, dt: f64) {
+        let standing = self.goals.front().map_or(true, Goal::is_standing);
+        let fighting = self.in_melee;
+        let desc = &self.troop_desc;
+        let troops = match &mut self.troops {
+            Some(troops) => troops,
+            None => return,
+        };
+
+        let recovery = if standing && !fighting { desc.stamina_recovery } else { 0. };
+        for i in 0..troops.len() {
+            if troops.condition[i] == Condition::Dead {
+                continue;
+            }
+            let drain = desc.exertion(troops.vel[i].len(), fighting, 0.);
+            troops.stamina[i] = (troops.stamina[i] + (recovery - drain) * dt as f32).clamp(0., 1.);
+        }
+    }
+
+    ///Fatigue is how spent the living are, morale falls with every man lost or hurt
+    pub fn update_condition(&mut self) {
+        let troops = match &self.troops {
+            Some(troops) if troops.len() > 0 => troops,
+            _ => return,
+        };
+
+        let mut alive = 0;
+        let mut stamina = 0.;
+        let mut health = 0.;
+        for i in 0..troops.len() {
+            if troops.condition[i] != Condition::Dead {
+                alive += 1;
+                stamina += troops.stamina[i];
+                health += troops.health[i];
+            }
+        }
+
+        self.fatigue = if alive > 0 { 1. - stamina / alive as f32 } else { 1. };
+        self.morale = health / troops.len() as f32;
+    }
+
+    ///Files for a front flen long, narrowed or widened to keep the rank count
+    ///within what the troop type allows
+    pub fn width_for_front(&self, flen: f64) -> usize {
+        let num = self.num_alive().max(1);
+        let files = ((flen / self.formation.spacing()).round() as usize + 1).min(num);
+
+        let min_ranks = (self.troop_desc.min_ranks as usize).max(1);
+        let max_ranks = match self.troop_desc.max_ranks {
+            0 => num,
+            r => (r as usize).max(min_ranks),
+        };
+
+        let ranks = (num + files - 1) / files;
+        let clamped = ranks.clamp(min_ranks, max_ranks);
+        if clamped == ranks {
+            files
+        } else {
+            (num + clamped - 1) / clamped
+        }
+    }
+
+    ///Slots the company would take for a front from p1 to p2 facing dir, what the drag preview shows
+    pub fn front_slots(&self, p1: Vec2f, p2: Vec2f, dir: Vec2f, rng: &mut SimRng) -> Vec<Vec2f> {
+        let mut formation = self.formation;
+        formation.set_width(self.width_for_front((p2 - p1).len()));
+
+        let anchor = (p1 + p2) * 0.5;
+        let dir = dir.normalise();
+
+        formation
+            .offsets(self.num_alive(), rng)
+            .iter()
+            .map(|o| anchor + Vec2f { x: -o.y, y: o.x }.rot_align(dir))
+            .collect()
+    }
+
+    ///Point of the front goal the formation is anchored to, and the facing there
+    pub fn goal_anchor(&self) -> (Vec2f, Vec2f) {
+        match self.goals.front() {
+            Some(Goal::Idle(pos)) => (*pos, self.direction),
+            Some(Goal::Move(pos, dir)) => (*pos, *dir),
+            Some(Goal::Face(dir)) => (self.center, *dir),
+            Some(Goal::Path(path, _)) => (path.point_at(self.path_progress), path.tangent_at(self.path_progress)),
+            Some(Goal::Column(pos, _)) => (*pos, *pos - self.center),
+            Some(Goal::Front(p1, p2, dir)) => ((*p1 + *p2) * 0.5, *dir),
+            Some(Goal::Hold) | None => (self.center, self.direction),
+        }
+    }
+
+    ///Lays out formation_positions around the anchor of the front goal.
+    ///Formation offsets are in the local frame: x along the front, y rank depth
+    pub fn form_up(&mut self, rng: &mut SimRng) {
+        //march facing the way we go, next_goal turns to the ordered facing on arrival
+        let mut march = None;
+        if let Some(Goal::Move(pos, dir)) = self.goals.front() {
+            let heading = *pos - self.center;
+            if heading.len() > MARCH_MIN_DIST && heading.angle_to(*dir).abs() > WHEEL_MIN_ANGLE {
+                march = Some(heading);
+            }
+        }
+
+        //a fresh path starts from where the front rank stands
+        if self.path_progress == 0. {
+            let front_center = self.center + self.direction * (self.formation.depth(self.num_alive()) / 2.);
+            if let Some(Goal::Path(path, _)) = self.goals.front_mut() {
+                path.set_start(front_center);
+            }
         }
+
+        let (anchor, dir) = self.goal_anchor();
+        let dir = march.unwrap_or(dir);
+        let in_place = matches!(self.goals.front(), Some(Goal::Hold) | Some(Goal::Face(_)) | None);
+
+        if let Some(Goal::Front(p1, p2, _)) = self.goals.front() {
+            let width = self.width_for_front((*p2 - *p1).len());
+            self.formation.set_width(width);
+        }
+
+        if let Some(Goal::Column(..)) = self.goals.front() {
+            if dir.len() > 0. {
+                self.direction = dir.normalise();
+            }
+            self.trail.clear();
+            self.trail.push_front(self.center);
+            self.lay_column();
+            self.reform = false;
+            return;
+        }
+
+        //a formed line turns as a whole before it moves off,
+        //an about-face is every boid turning where it stands
+        if dir.len() > 0. && self.is_formed() {
+            let angle = self.direction.angle_to(dir);
+            let about_face = matches!(self.goals.front(), Some(Goal::Face(_)))
+                && angle.abs() > std::f64::consts::FRAC_PI_2;
+
+            if !about_face && angle.abs() > WHEEL_MIN_ANGLE {
+                self.start_wheel(dir.normalise(), angle);
+                self.reform = false;
+                return;
+            }
+        }
+
+        if dir.len() > 0. {
+            self.direction = dir.normalise();
+        }
+
+        let num = self.num_alive();
+        let offsets = match self.goals.front() {
+            Some(Goal::Idle(_)) => Formation::Idle { radius: self.idle_radius() }.offsets(num, rng),
+            _ => self.formation.offsets(num, rng),
+        };
+        let mut slots: Vec<Vec2f> = offsets
+            .iter()
+            .map(|o| anchor + Vec2f { x: -o.y, y: o.x }.rot_align(self.direction))
+            .collect();
+
+        //standing orders keep the block where it is instead of stepping forward to the centre
+        if in_place && !slots.is_empty() {
+            let mut sum = Vec2f::default();
+            for pos in slots.iter() {
+                sum += *pos;
+            }
+            let shift = self.center - sum * (1. / slots.len() as f64);
+            for pos in slots.iter_mut() {
+                *pos += shift;
+            }
+        }
+
+        self.give_slots(slots, true);
+
+        //keep the assigned order in the local frame, the path moves the slots every tick
+        if let Some(Goal::Path(..)) = self.goals.front() {
+            let angle = -f64::atan2(self.direction.y, self.direction.x);
+            self.path_slots = self.formation_positions
+                .iter()
+                .map(|p| {
+                    let q = (*p - anchor).rotate(angle);
+                    Vec2f { x: q.y, y: -q.x }
+                })
+                .collect();
+        }
+
+        self.reform = false;
     }
 
-    pub fn calculate_formation(&mut self, flen: f64) {
-        let form_width = (flen / FORMATION_SPACING).round() as usize;
+    ///Hands the slots out to the living, the dead keep the ground they lie on.
+    ///Slots go to the nearest boids when assign is set, in boid order otherwise
+    fn give_slots(&mut self, mut slots: Vec<Vec2f>, assign: bool) {
+        let troops = match &self.troops {
+            Some(troops) => troops,
+            None => return,
+        };
+        let living: Vec<usize> = (0..troops.len()).filter(|i| troops.condition[*i] != Condition::Dead).collect();
+
+        if assign {
+            let pos: Vec<Vec2f> = living.iter().map(|i| troops.pos[*i]).collect();
+            assign_slots(&pos, &mut slots);
+        }
 
-        for (i, pos) in self.formation_positions.iter_mut().enumerate() {
-            *pos = (self.formation)(i, form_width);
+        let mut positions = troops.pos.clone();
+        for (i, slot) in living.iter().zip(slots) {
+            positions[*i] = slot;
+        }
+        self.slotted = living.len();
+        self.formation_positions = positions;
+    }
+
+    fn is_dead(&self, i: usize) -> bool {
+        self.troops.as_ref().map_or(true, |t| t.condition[i] == Condition::Dead)
+    }
+
+    ///Centre and reach of the living, the fallen are left behind
+    fn update_center(&mut self) {
+        if let Some(troops) = &self.troops {
+            let living: Vec<Vec2f> = troops.living().collect();
+            if living.is_empty() {
+                return;
+            }
+
+            let mut sum = Vec2f::default();
+            for pos in living.iter() {
+                sum += *pos;
+            }
+            self.center = sum * (1. / living.len() as f64);
+
+            let center = self.center;
+            self.select_radius = living.iter().map(|p| (*p - center).len()).fold(0., f64::max);
+        }
+    }
+
+    ///Boid ids follow the company id, see rebuild_index
+    pub fn owns(&self, id: WorldId) -> bool {
+        id > self.id && id <= self.id + self.num_boids()
+    }
+
+    ///Room for an at ease crowd of this company
+    fn idle_radius(&self) -> f64 {
+        FORMATION_SPACING * IDLE_LOOSENESS * (self.num_alive() as f64 / std::f64::consts::PI).sqrt()
+    }
+
+    ///Idle slots wander a little around the anchor, boids mill after them
+    fn drift_idle(&mut self, anchor: Vec2f, dt: f64, rng: &mut SimRng) {
+        let radius = self.idle_radius();
+
+        for pos in self.formation_positions.iter_mut() {
+            let drift = Vec2f {
+                x: rng.gen::<f64>() * 2. - 1.,
+                y: rng.gen::<f64>() * 2. - 1.,
+            } * (IDLE_DRIFT * dt);
+
+            if (*pos + drift - anchor).len() < radius {
+                *pos += drift;
+            }
+        }
+    }
+
+    ///Living boids are close to the slots they have
+    pub fn is_formed(&self) -> bool {
+        match &self.troops {
+            Some(troops) if troops.len() == self.formation_positions.len() => {
+                let mut alive = 0;
+                let mut total = 0.;
+                for i in 0..troops.len() {
+                    if troops.condition[i] != Condition::Dead {
+                        alive += 1;
+                        total += (troops.pos[i] - self.formation_positions[i]).len();
+                    }
+                }
+                alive > 0 && total < alive as f64 * FORMATION_SPACING
+            }
+            _ => false,
+        }
+    }
+
+    ///Up to a quarter turn the line wheels on the flank it turns towards,
+    ///anything more pivots on the centre
+    fn start_wheel(&mut self, facing: Vec2f, angle: f64) {
+        let pivot = if angle.abs() <= std::f64::consts::FRAC_PI_2 {
+            let side = Vec2f { x: 0., y: angle.signum() }.rot_align(self.direction);
+            self.center + side * (self.formation.frontage() / 2.)
+        } else {
+            self.center
+        };
+
+        let outer = self.formation_positions.iter()
+            .enumerate()
+            .filter(|(i, _)| !self.is_dead(*i))
+            .map(|(_, p)| (*p - pivot).len())
+            .fold(FORMATION_SPACING, f64::max);
+
+        self.wheel = Some(Wheel { pivot, facing, outer });
+    }
+
+    ///Rotates the slots around the pivot no faster than the outer file can walk
+    fn advance_wheel(&mut self, dt: f64) {
+        let wheel = match self.wheel {
+            Some(w) => w,
+            None => return,
+        };
+
+        let remaining = self.direction.angle_to(wheel.facing);
+        let step = remaining.abs().min(WHEEL_PACE * self.troop_desc.max_speed() / wheel.outer * dt).copysign(remaining);
+
+        for pos in self.formation_positions.iter_mut() {
+            *pos = wheel.pivot + (*pos - wheel.pivot).rotate(step);
+        }
+        self.direction = self.direction.rotate(step);
+
+        if (remaining - step).abs() < 1e-3 {
+            self.direction = wheel.facing;
+            self.wheel = None;
+            //lay out again at the goal, now facing the right way
+            self.reform = true;
+        }
+    }
+
+    ///Moves the anchor along the front Path goal, slower on tight curves, and lays
+    ///the slots around it. Returns true once the end is reached
+    fn advance_path(&mut self, dt: f64) -> bool {
+        let (anchor, dir, done) = match self.goals.front() {
+            Some(Goal::Path(path, _)) => {
+                let curvature = path.curvature_at(self.path_progress);
+                let half_front = self.formation.frontage() / 2.;
+                let speed = self.troop_desc.max_speed() * WHEEL_PACE / (1. + CURVE_SLOWDOWN * curvature * half_front);
+
+                self.path_progress = (self.path_progress + speed * dt).min(path.length());
+                (
+                    path.point_at(self.path_progress),
+                    path.tangent_at(self.path_progress),
+                    self.path_progress >= path.length(),
+                )
+            }
+            _ => return true,
+        };
+
+        if dir.len() > 0. {
+            self.direction = dir;
+        }
+        if self.path_slots.len() == self.formation_positions.len() {
+            for (pos, o) in self.formation_positions.iter_mut().zip(self.path_slots.iter()) {
+                *pos = anchor + Vec2f { x: -o.y, y: o.x }.rot_align(self.direction);
+            }
+        }
+
+        done
+    }
+
+    ///Column head and the direction of the column at a distance behind it.
+    ///Past the end of the trail the column extends straight back
+    fn trail_point(&self, back: f64) -> (Vec2f, Vec2f) {
+        let mut left = back;
+        let mut dir = self.direction;
+
+        for (a, b) in self.trail.iter().zip(self.trail.iter().skip(1)) {
+            let seg = *a - *b;
+            let len = seg.len();
+            if len == 0. {
+                continue;
+            }
+
+            dir = seg.normalise();
+            if left <= len {
+                return (*a - dir * left, dir);
+            }
+            left -= len;
+        }
+
+        let tail = *self.trail.back().unwrap_or(&self.center);
+        (tail - dir * left, dir)
+    }
+
+    ///Ranks of COLUMN_WIDTH files follow the head along the trail
+    fn lay_column(&mut self) {
+        let num = self.num_alive();
+        let spacing = self.formation.spacing();
+        let half_front = (COLUMN_WIDTH - 1) as f64 * spacing / 2.;
+
+        let slots: Vec<Vec2f> = (0..num)
+            .map(|i| {
+                let (p, dir) = self.trail_point((i / COLUMN_WIDTH) as f64 * spacing);
+                p + Vec2f { x: 0., y: (i % COLUMN_WIDTH) as f64 * spacing - half_front }.rot_align(dir)
+            })
+            .collect();
+
+        self.give_slots(slots, false);
+    }
+
+    ///Moves the column head towards the target and lays the slots behind it.
+    ///Returns true once the head has arrived
+    fn advance_column(&mut self, target: Vec2f, dt: f64) -> bool {
+        let head = *self.trail.front().unwrap_or(&self.center);
+        let d = target - head;
+        let step = d.clampret(self.troop_desc.max_speed() * COLUMN_PACE * dt);
+
+        if step.len() > 0. {
+            self.trail.push_front(head + step);
+            self.direction = d.normalise();
+        }
+
+        //forget the trail behind the last rank
+        let ranks = (self.num_alive() + COLUMN_WIDTH - 1) / COLUMN_WIDTH;
+        let length = (ranks + 1) as f64 * self.formation.spacing();
+        let mut walked = 0.;
+        let mut keep = self.trail.len();
+        for (k, (a, b)) in self.trail.iter().zip(self.trail.iter().skip(1)).enumerate() {
+            walked += (*a - *b).len();
+            if walked > length {
+                keep = k + 2;
+                break;
+            }
+        }
+        self.trail.truncate(keep);
+
+        self.lay_column();
+
+        d.len() < DIST_MARGIN
+    }
+
+    fn next_goal(&mut self) {
+        if self.goals.len() == 1 && self.goals.front().map_or(false, Goal::is_standing) {
+            return;
+        }
+        //scenarios may set patrol with only the goal queue filled in
+        if self.patrol && self.route.is_empty() {
+            self.route = self.queued_route();
+        }
+
+        match self.goals.pop_front() {
+            //deploy into line where the column ended
+            Some(Goal::Column(pos, Some(dir))) => self.goals.push_front(Goal::Move(pos, dir)),
+            //face the ordered way at the end of a march
+            Some(Goal::Move(_, dir)) | Some(Goal::Path(_, dir)) => {
+                if self.direction.angle_to(dir).abs() > WHEEL_MIN_ANGLE {
+                    self.goals.push_front(Goal::Face(dir))
+                } else if dir.len() > 0. {
+                    //too small a turn to wheel for, the ranks just square up
+                    self.direction = dir.normalise();
+                }
+            }
+            _ => {}
+        }
+        self.path_progress = 0.;
+        if self.goals.is_empty() {
+            if self.patrol && !self.route.is_empty() {
+                for goal in self.route.clone() {
+                    self.queue_order(goal);
+                }
+            } else {
+                self.goals.push_back(Goal::Hold)
+            }
+        }
+        self.reform = true;
+    }
+
+    ///Starting a patrol keeps what is already queued as the start of the route
+    pub fn set_patrol(&mut self, patrol: bool) {
+        if patrol && !self.patrol {
+            self.route = self.queued_route();
+        } else if !patrol {
+            self.route.clear();
+        }
+        self.patrol = patrol;
+    }
+
+    ///(index into goals, position) of every queued goal that takes the unit somewhere
+    pub fn goal_markers(&self) -> Vec<(usize, Vec2f)> {
+        self.goals
+            .iter()
+            .enumerate()
+            .filter(|(_, g)| !g.is_standing())
+            .filter_map(|(i, g)| g.target().map(|t| (i, t)))
+            .collect()
+    }
+
+    ///(index into goals, marker position) of the marker under pos
+    pub fn goal_at(&self, pos: Vec2f) -> Option<(usize, Vec2f)> {
+        self.goal_markers()
+            .into_iter()
+            .find(|(_, m)| (*m - pos).len() < GOAL_MARKER_SIZE)
+    }
+
+    pub fn move_goal(&mut self, i: usize, pos: Vec2f) {
+        let goal = match self.goals.get_mut(i) {
+            Some(goal) => goal,
+            None => return,
+        };
+        goal.set_target(pos);
+        //a path being marched keeps its progress, only the end moves
+        let restart = i == 0 && !matches!(goal, Goal::Path(..));
+        self.goals_edited(restart);
+    }
+
+    pub fn remove_goal(&mut self, i: usize) -> Option<Goal> {
+        let goal = self.goals.remove(i)?;
+        if self.goals.is_empty() {
+            self.goals.push_back(Goal::Hold);
+        }
+        self.goals_edited(i == 0);
+        Some(goal)
+    }
+
+    pub fn insert_goal(&mut self, i: usize, goal: Goal) {
+        self.goals.insert(i.min(self.goals.len()), goal);
+        self.goals_edited(i == 0);
+    }
+
+    fn goals_edited(&mut self, restart: bool) {
+        if restart {
+            self.wheel = None;
+            self.path_progress = 0.;
+            self.reform = true;
+        }
+        if self.patrol {
+            self.route = self.queued_route();
+        }
+    }
+
+    fn queued_route(&self) -> Vec<Goal> {
+        self.goals.iter().filter(|g| !g.is_standing()).cloned().collect()
+    }
+
+    ///Queued moves join the move before them into one curved path
+    fn queue_order(&mut self, goal: Goal) {
+        if let Goal::Move(pos, dir) = goal {
+            let n = self.goals.len();
+            let start = if n >= 2 {
+                self.goals[n - 2].target().unwrap_or(self.center)
+            } else {
+                self.center
+            };
+
+            match self.goals.back_mut() {
+                Some(Goal::Path(path, facing)) => {
+                    path.push(pos);
+                    *facing = dir;
+                    return;
+                }
+                Some(Goal::Move(p, _)) => {
+                    let path = Path::new(vec![start, *p, pos]);
+                    *self.goals.back_mut().unwrap() = Goal::Path(path, dir);
+                    if n == 1 {
+                        self.path_progress = 0.;
+                        self.reform = true;
+                    }
+                    return;
+                }
+                _ => {}
+            }
         }
+
+        self.goals.push_back(goal);
     }
 
-    pub fn p_b(&mut self, dt: f64) {
+    pub fn p_b(&mut self, dt: f64, rng: &mut SimRng, index: &SpatialGrid<WorldId>) {
+        if self.reform || self.formation_positions.len() != self.num_boids() || self.slotted != self.num_alive() {
+            self.form_up(rng);
+        }
+
+        let column_arrived = match self.goals.front() {
+            Some(Goal::Column(target, _)) => {
+                let target = *target;
+                self.advance_column(target, dt)
+            }
+            _ => true,
+        };
+        //Warm keeps the collision checks, only Hot spends time on per boid detail
+        let hot = self.lod == ContainerState::Hot;
+        if let Some(Goal::Idle(anchor)) = self.goals.front() {
+            let anchor = *anchor;
+            if hot {
+                self.drift_idle(anchor, dt, rng);
+            }
+        }
+        let path_done = self.wheel.is_some() || self.advance_path(dt);
+        self.advance_wheel(dt);
+        //the goal is not done while the slots are still moving
+        let settled = column_arrived && path_done && self.wheel.is_none() && !self.reform;
+
+        if self.lod == ContainerState::Cold {
+            self.p_cold(dt, settled);
+            return;
+        }
+
+        let troops = match &mut self.troops {
+            Some(troops) => troops,
+            None => return,
+        };
+
         //calc cum_dist
         let mut cum_dist = 0.0;
+        let num = troops.len();
+        let alive = troops.num_alive();
 
-        //get formation, rotate according to heading
-        //after forming, rotate formation only within 1 quadrant, other dirs handled by rotating individual boids
+        //get formation, rotate according to heading, see advance_wheel
 
-        //let iter = ;
+        let id = self.id;
+        let last = id + num;
+        let idle = matches!(self.goals.front(), Some(Goal::Idle(_)));
+        let hold = matches!(self.goals.front(), Some(Goal::Hold));
+        let facing = f64::atan2(self.direction.y, self.direction.x);
 
-        let mut slice = self.ent.as_mut_slice();
-
-        let slen = slice.len();
+        let speed = self.troop_desc.max_speed();
+        let acc = self.troop_desc.max_acc();
+        let (max_speed, max_acc, separation_gain) = if idle {
+            (speed * IDLE_PACE, acc, SEPARATION_GAIN)
+        } else if hold {
+            (speed, acc * HOLD_BRACE, SEPARATION_GAIN * HOLD_SEPARATION)
+        } else {
+            (speed, acc, SEPARATION_GAIN)
+        };
 
+        for (i, boid) in troops.iter_mut().enumerate() {
+            //the dead stay where they fell
+            if *boid.condition == Condition::Dead {
+                *boid.prev_pos = *boid.pos;
+                *boid.vel = Vec2f::default();
+                continue;
+            }
+            let (max_speed, max_acc) = if *boid.condition == Condition::Wounded {
+                (max_speed * WOUNDED_PACE, max_acc * WOUNDED_PACE)
+            } else {
+                (max_speed, max_acc)
+            };
+            let spent = fatigue_factor(1. - *boid.stamina);
+            let (max_speed, max_acc) = (max_speed * spent, max_acc * spent);
 
-        for (i, boid) in slice.iter_mut().enumerate() {
             let d = self.formation_positions[i] - *boid.pos;
 
             let dist = d.len();
             cum_dist += dist;
 
-            //let slice = self.ent.slice_mut(i+1..self.ent.len());
+            //index holds positions at the start of the tick, so the boid order does not matter
+            //slots are FORMATION_SPACING apart, further than DIST_REPEL, so this vanishes once formed
+            let neighbours = index
+                .query_radius(*boid.pos, DIST_REPEL)
+                .filter(|(j, _)| *j > id && *j <= last && *j != id + i + 1)
+                .map(|(_, p)| p);
+            let push = separation(*boid.pos, neighbours, DIST_REPEL);
 
-            /*for j in i+1..slen {
-                slice.pos[j];
-            }*/
+            //fastest speed that can still brake to a stop at the slot
+            let arrive_speed = max_speed.min((2. * max_acc * dist).sqrt());
+            let target_vel = (d.normalise() * arrive_speed + push * separation_gain).clampret(max_speed);
 
-            *boid.vel += d * dt;
-            boid.vel.clamp(VEL_MAX.min(dist));
+            let mut dv = target_vel - *boid.vel;
+            dv.clamp(max_acc * dt);
+            *boid.vel += dv;
 
+            *boid.prev_pos = *boid.pos;
             *boid.pos += *boid.vel * dt;
 
-            let heading: f64 = f64::atan2(boid.vel.y, boid.vel.x);
+            if !hot {
+                continue;
+            }
+            *boid.state = BoidState::classify(boid.vel.len(), target_vel.len(), idle);
 
-            if heading.is_normal() {
+            //standing boids face the way the unit faces
+            let heading: f64 = if *boid.state == BoidState::Stationary {
+                facing
+            } else {
+                f64::atan2(boid.vel.y, boid.vel.x)
+            };
+
+            if heading.is_finite() {
                 *boid.r = heading;
             }
         }
@@ -168,19 +962,174 @@ impl BasicUnit {
         //the more the curve, the slower the step? or adjust speed manually
         //kinematic step
         //do collision detection, from inside out?
-        if cum_dist < self.ent.len() as f64 * DIST_MARGIN {
-            self.goals.pop_front();
-            if self.goals.is_empty() {
-                self.goals.push_back(Goal::Hold)
+        self.update_center();
+
+        if settled && cum_dist < alive as f64 * DIST_MARGIN {
+            self.next_goal();
+        }
+    }
+
+    ///Cold step: the company moves as a rigid aggregate towards the centre of its slots,
+    ///boids keep their offsets and nothing is checked per boid
+    fn p_cold(&mut self, dt: f64, settled: bool) {
+        let mut num = 0;
+        let mut target = Vec2f::default();
+        for (i, pos) in self.formation_positions.iter().enumerate() {
+            if !self.is_dead(i) {
+                num += 1;
+                target += *pos;
+            }
+        }
+        if num == 0 {
+            return;
+        }
+        let d = target * (1. / num as f64) - self.center;
+        let dist = d.len();
+
+        let spent = fatigue_factor(self.fatigue);
+        let (speed, acc) = (self.troop_desc.max_speed() * spent, self.troop_desc.max_acc() * spent);
+        let vel = d.normalise() * speed.min((2. * acc * dist).sqrt());
+        let step = (vel * dt).clampret(dist);
+        self.center += step;
+
+        if let Some(troops) = &mut self.troops {
+            for boid in troops.iter_mut() {
+                if *boid.condition == Condition::Dead {
+                    continue;
+                }
+                *boid.prev_pos = *boid.pos;
+                *boid.pos += step;
+                *boid.vel = vel;
             }
         }
+
+        if settled && dist < DIST_MARGIN {
+            self.next_goal();
+        }
+    }
+}
+
+impl Container for BasicUnit {
+    fn cur_state(&self) -> ContainerState {
+        self.lod
+    }
+
+    fn to_cold(&mut self) {
+        self.lod = ContainerState::Cold;
+    }
+
+    fn to_warm(&mut self) {
+        self.lod = ContainerState::Warm;
+    }
+
+    ///Boids drifted as a block while cold, the slots are laid out again around them
+    fn to_hot(&mut self) {
+        if self.lod == ContainerState::Cold {
+            self.reform = true;
+        }
+        self.lod = ContainerState::Hot;
+    }
+}
+
+///What a command needs to know about a subordinate to give it a place in line
+#[derive(Debug, Copy, Clone)]
+pub struct Member {
+    pub center: Vec2f,
+    pub strength: usize,
+    pub frontage: f64,
+}
+
+impl Member {
+    pub fn of(company: &BasicUnit) -> Self {
+        Member {
+            center: company.center,
+            strength: company.num_alive(),
+            frontage: company.formation.frontage(),
+        }
+    }
+}
+
+///Member indices sorted by where they stand along axis, so nobody crosses over
+pub fn members_along(members: &[Member], axis: Vec2f) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..members.len()).collect();
+    order.sort_by(|a, b| members[*a].center.dot(axis).total_cmp(&members[*b].center.dot(axis)));
+    order
+}
+
+///Shares the front from p1 to p2 out abreast by strength, goals come back in members order
+pub fn share_front(p1: Vec2f, p2: Vec2f, dir: Vec2f, members: &[Member]) -> Vec<Goal> {
+    let n = members.len();
+    let mut goals = vec![Goal::Hold; n];
+    let line = p2 - p1;
+    let along = if line.len() > 0. { line.normalise() } else { Vec2f { x: -dir.y, y: dir.x }.normalise() };
+
+    let strength: usize = members.iter().map(|m| m.strength.max(1)).sum();
+    let usable = (line.len() - COMPANY_GAP * n.saturating_sub(1) as f64).max(0.);
+
+    let mut s = 0.;
+    for i in members_along(members, along) {
+        let share = usable * members[i].strength.max(1) as f64 / strength as f64;
+        let a = p1 + along * s;
+        goals[i] = Goal::Front(a, a + along * share, dir);
+        s += share + COMPANY_GAP;
+    }
+    goals
+}
+
+///Places members abreast around pos, each keeping its own frontage
+pub fn share_move(pos: Vec2f, dir: Vec2f, members: &[Member]) -> Vec<Goal> {
+    let n = members.len();
+    let mut goals = vec![Goal::Hold; n];
+    let dir = dir.normalise();
+    let across = Vec2f { x: -dir.y, y: dir.x };
+
+    let total = members.iter().map(|m| m.frontage).sum::<f64>() + COMPANY_GAP * n.saturating_sub(1) as f64;
+
+    let mut s = -total / 2.;
+    for i in members_along(members, across) {
+        let width = members[i].frontage;
+        goals[i] = Goal::Move(pos + across * (s + width / 2.), dir);
+        s += width + COMPANY_GAP;
+    }
+    goals
+}
+
+///Share of speed, acceleration and striking power left at a fatigue from 0 to 1
+pub fn fatigue_factor(fatigue: f32) -> f64 {
+    1. - FATIGUE_PENALTY * fatigue.clamp(0., 1.) as f64
+}
+
+///Facing for a front from p1 to p2: perpendicular to it, away from where the unit is now
+pub fn front_facing(p1: Vec2f, p2: Vec2f, center: Vec2f) -> Vec2f {
+    let line = p2 - p1;
+    let ahead = (p1 + p2) * 0.5 - center;
+
+    if line.len() == 0. {
+        return ahead.normalise();
+    }
+
+    let perp = Vec2f { x: -line.y, y: line.x }.normalise();
+    if perp.dot(ahead) < 0. {
+        -perp
+    } else {
+        perp
     }
 }
 
 const BASE_UNIT_CAPACITY: usize = 256;
 
+///Company id of a boid id
+pub fn company_of(boid: WorldId) -> WorldId {
+    (boid - 1) / BASE_UNIT_CAPACITY * BASE_UNIT_CAPACITY
+}
+
 pub static NUM_BASIC_UNITS: AtomicUsize = AtomicUsize::new(0);
 
+///Keeps ids generated after a load clear of the loaded ones
+pub fn reserve_ids(max_id: WorldId) {
+    NUM_BASIC_UNITS.fetch_max(max_id / BASE_UNIT_CAPACITY, Ordering::Relaxed);
+}
+
 impl Identifiable for BasicUnit {
     fn generate_id(&self) -> WorldId {
         let nc = NUM_BASIC_UNITS.fetch_add(1, Ordering::Relaxed);
@@ -192,12 +1141,6 @@ impl Identifiable for BasicUnit {
     }
 }
 
-impl Drawable for BasicUnit {
-    fn draw<G>(&self, c: Context, g: &mut G) where G: Graphics {
-        todo!()
-    }
-}
-
 impl Clickable for BasicUnit {
     fn is_on_screen(&self) -> bool {
         todo!()
@@ -206,40 +1149,70 @@ impl Clickable for BasicUnit {
     fn on_click(&self) {
         todo!()
     }
+
+    fn is_in_bounds(&self, p: Vec2f) -> bool {
+        (p - self.center).len() < self.select_radius
+    }
 }
 
 impl Selectable for BasicUnit {
+    fn select(&self) {
+        todo!()
+    }
+
     fn deselect(&self) {
         todo!()
     }
 }
 
 impl Controllable for BasicUnit {
-    fn new_order(&self, goal: Goal) {
-        todo!()
+    fn new_order(&mut self, goal: Goal) {
+        self.goals.clear();
+        self.route.clear();
+        if self.patrol {
+            self.route.push(goal.clone());
+        }
+        self.goals.push_back(goal);
+        self.wheel = None;
+        self.path_progress = 0.;
+        self.reform = true;
     }
 
-    fn add_order(&self, goal: Goal) {
-        todo!()
+    fn add_order(&mut self, goal: Goal) {
+        //a standing Hold or Idle gives way to the queued order
+        if self.goals.len() == 1 && self.goals.front().map_or(false, Goal::is_standing) {
+            self.new_order(goal);
+            return;
+        }
+
+        if self.patrol {
+            self.route.push(goal.clone());
+        }
+        self.queue_order(goal);
     }
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct DrillStep {
-    company_type_id: usize,
-    company_formation: FormationFunction,
-    pos: Vec2f,
-    dir: Vec2f,
-    time: f32
+///Gap left between neighbouring companies of a battalion
+pub const COMPANY_GAP: f64 = FORMATION_SPACING * 2.;
+
+///How a battalion places its companies for a front or a move
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Deployment {
+    ///abreast, the front is shared out by strength
+    Line,
+    ///one behind the other, each on the full front
+    Column,
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct Drill {
-    steps: Vec<DrillStep>
+impl Default for Deployment {
+    fn default() -> Self {
+        Deployment::Line
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct CompositeUnit {
+    pub id: WorldId,
     pub center: Vec2f,
     pub direction: Vec2f,
     pub select_radius: f64,
@@ -253,23 +1226,229 @@ pub struct CompositeUnit {
     pub fatigue: f32,
     pub morale: f32,
 
+    ///centres of the company slots from the last order
     pub formation_positions: Vec<Vec2f>,
+    #[serde(default)]
+    pub deployment: Deployment,
     pub known_drills: Vec<Drill>,
+    ///drill being played, kept after it ends to report how it went
+    #[serde(default)]
+    pub drill: Option<DrillRun>,
 
     pub troops: Vec<BasicUnit>,
 }
 
 impl CompositeUnit {
-    fn assemble() -> CompositeUnit {
-        todo!()
+    ///Battalion of num_companies fresh companies side by side around pos
+    pub fn new(pos: Vec2f, num_companies: usize, units_per_company: usize, rng: &mut SimRng) -> Self {
+        let across = Vec2f { x: 0., y: 1. };
+        let frontage = Formation::phalanx((units_per_company as f64).sqrt().ceil() as usize).frontage();
+        let step = frontage + COMPANY_GAP;
+        let first = -(num_companies.saturating_sub(1) as f64) * step / 2.;
+
+        let companies = (0..num_companies)
+            .map(|i| BasicUnit::new(pos + across * (first + i as f64 * step), units_per_company, rng))
+            .collect();
+
+        CompositeUnit::assemble(companies)
     }
 
-    fn attach_company(&mut self, c: &BasicUnit) {
+    ///Battalion made of existing companies, in the order given
+    pub fn assemble(companies: Vec<BasicUnit>) -> CompositeUnit {
+        let mut battalion = CompositeUnit {
+            id: 0,
+            center: Vec2f::default(),
+            direction: companies.first().map_or(Vec2f { x: 1., y: 0. }, |c| c.direction),
+            select_radius: 0.,
+            interaction_radius: 0.,
+            goals: VecDeque::from([Goal::Hold]),
+            selected: false,
+            fatigue: 0.,
+            morale: 1.,
+            formation_positions: vec![],
+            deployment: Deployment::default(),
+            known_drills: vec![],
+            drill: None,
+            troops: vec![],
+        };
+        battalion.id = battalion.generate_id();
+
+        for company in companies {
+            battalion.attach_company(company);
+        }
+        battalion
+    }
 
+    ///Plays known_drills[i] from where the battalion stands now
+    pub fn start_drill(&mut self, i: usize) -> bool {
+        if i >= self.known_drills.len() {
+            return false;
+        }
+        self.drill = Some(DrillRun::new(i, self.center, self.direction));
+        true
     }
 
-    fn detach_company(&mut self, id: WorldId) {
+    ///How the drill stands after this tick, None if none was started
+    pub fn advance_drill(&mut self, dt: f64) -> Option<DrillStatus> {
+        let run = self.drill.as_mut()?;
+        if run.status != DrillStatus::Running {
+            return Some(run.status);
+        }
 
+        let status = match self.known_drills.get(run.drill) {
+            Some(drill) => run.advance(drill, &mut self.troops, dt),
+            None => DrillStatus::Finished,
+        };
+        run.status = status;
+        Some(status)
+    }
+
+    pub fn update_center(&mut self) {
+        if self.troops.is_empty() {
+            return;
+        }
+
+        let mut sum = Vec2f::default();
+        for company in self.troops.iter() {
+            sum += company.center;
+        }
+        self.center = sum * (1. / self.troops.len() as f64);
+
+        let center = self.center;
+        self.select_radius = self.troops.iter()
+            .map(|c| (c.center - center).len() + c.select_radius)
+            .fold(0., f64::max);
+    }
+
+    ///Companies' fatigue and morale, weighted by the men left in them
+    pub fn update_condition(&mut self) {
+        let alive: usize = self.troops.iter().map(|c| c.num_alive()).sum();
+        if alive == 0 {
+            return;
+        }
+
+        let weight = |c: &BasicUnit| c.num_alive() as f32 / alive as f32;
+        self.fatigue = self.troops.iter().map(|c| c.fatigue * weight(c)).sum();
+        self.morale = self.troops.iter().map(|c| c.morale * weight(c)).sum();
+    }
+
+    ///Drops battalion goals once no company has that many left to do
+    fn retire_goals(&mut self) {
+        let left = self.troops.iter()
+            .map(|c| c.goals.iter().filter(|g| !g.is_standing()).count())
+            .max()
+            .unwrap_or(0);
+
+        while self.goals.len() > left.max(1) {
+            self.goals.pop_front();
+        }
+        if left == 0 && !self.goals.front().map_or(false, Goal::is_standing) {
+            self.goals.clear();
+            self.goals.push_back(Goal::Hold);
+        }
+    }
+
+    fn members(&self) -> Vec<Member> {
+        self.troops.iter().map(Member::of).collect()
+    }
+
+    ///Company indices sorted by where they stand along axis, so nobody crosses over
+    fn companies_along(&self, axis: Vec2f) -> Vec<usize> {
+        members_along(&self.members(), axis)
+    }
+
+    ///Where the battalion stands once its queued goals are done
+    fn last_target(&self) -> Vec2f {
+        self.goals.iter().rev().find_map(Goal::target).unwrap_or(self.center)
+    }
+
+    ///Splits a battalion goal into one goal per company, indexed like troops
+    pub fn company_goals(&self, goal: &Goal) -> Vec<Goal> {
+        match goal {
+            Goal::Front(p1, p2, dir) => self.split_front(*p1, *p2, *dir),
+            Goal::Move(pos, dir) => self.split_move(*pos, *dir),
+            //the battalion turns as a whole, companies take their new places around its centre
+            Goal::Face(dir) => self.split_move(self.last_target(), *dir),
+            Goal::Path(path, dir) => self.split_move(path.end(), *dir),
+            Goal::Column(pos, deploy) => {
+                let heading = deploy.unwrap_or(*pos - self.last_target()).normalise();
+                //the company nearest the destination leads
+                let mut goals = vec![Goal::Hold; self.troops.len()];
+                let mut back = 0.;
+                for i in self.companies_along(heading).into_iter().rev() {
+                    let company = &self.troops[i];
+                    goals[i] = Goal::Column(*pos - heading * back, *deploy);
+                    back += company.formation.depth(company.num_alive()) + COMPANY_GAP;
+                }
+                goals
+            }
+            Goal::Idle(pos) => self.troops.iter()
+                .map(|c| Goal::Idle(*pos + c.center - self.center))
+                .collect(),
+            Goal::Hold => vec![Goal::Hold; self.troops.len()],
+        }
+    }
+
+    fn split_front(&self, p1: Vec2f, p2: Vec2f, dir: Vec2f) -> Vec<Goal> {
+        if self.deployment == Deployment::Line {
+            return share_front(p1, p2, dir, &self.members());
+        }
+
+        let mut goals = vec![Goal::Hold; self.troops.len()];
+        let line = p2 - p1;
+        let back_dir = -dir.normalise();
+        let mut back = 0.;
+        for i in self.companies_along(dir).into_iter().rev() {
+            let company = &self.troops[i];
+            let offset = back_dir * back;
+            goals[i] = Goal::Front(p1 + offset, p2 + offset, dir);
+
+            let files = company.width_for_front(line.len());
+            let ranks = (company.num_alive().max(1) + files - 1) / files;
+            back += (ranks - 1) as f64 * company.formation.spacing() + COMPANY_GAP;
+        }
+        goals
+    }
+
+    ///Companies keep their own frontage and arrange around pos
+    fn split_move(&self, pos: Vec2f, dir: Vec2f) -> Vec<Goal> {
+        if self.deployment == Deployment::Line {
+            return share_move(pos, dir, &self.members());
+        }
+
+        let mut goals = vec![Goal::Hold; self.troops.len()];
+        let dir = dir.normalise();
+        let mut back = 0.;
+        for i in self.companies_along(dir).into_iter().rev() {
+            let company = &self.troops[i];
+            let depth = company.formation.depth(company.num_alive());
+            goals[i] = Goal::Move(pos - dir * (back + depth / 2.), dir);
+            back += depth + COMPANY_GAP;
+        }
+        goals
+    }
+
+    pub fn attach_company(&mut self, company: BasicUnit) {
+        self.interaction_radius = self.interaction_radius.max(company.interaction_radius);
+        self.troops.push(company);
+        self.composition_changed();
+    }
+
+    pub fn detach_company(&mut self, id: WorldId) -> Option<BasicUnit> {
+        let i = self.troops.iter().position(|c| c.id == id)?;
+        let company = self.troops.remove(i);
+
+        self.interaction_radius = self.troops.iter().map(|c| c.interaction_radius).fold(0., f64::max);
+        self.composition_changed();
+        Some(company)
+    }
+
+    ///A running drill addresses companies by their place in troops, which no longer holds
+    fn composition_changed(&mut self) {
+        if self.drill.as_ref().map_or(false, |d| d.status == DrillStatus::Running) {
+            self.drill = None;
+        }
+        self.update_center();
     }
 }
 
@@ -278,8 +1457,10 @@ impl Identifiable for CompositeUnit {
         self.id
     }
 
+    ///Battalions take a block from the company counter so no id is ever shared
     fn generate_id(&self) -> WorldId {
-        todo!()
+        let nc = NUM_BASIC_UNITS.fetch_add(1, Ordering::Relaxed);
+        BASE_UNIT_CAPACITY * (nc + 1)
     }
 }
 
@@ -291,20 +1472,226 @@ impl Clickable for CompositeUnit {
     fn on_click(&self) {
         todo!()
     }
+
+    fn is_in_bounds(&self, p: Vec2f) -> bool {
+        (p - self.center).len() < self.select_radius
+    }
 }
 
 impl Selectable for CompositeUnit {
+    fn select(&self) {
+        todo!()
+    }
+
     fn deselect(&self) {
         todo!()
     }
 }
 
+///Battalion goals are kept for display, the companies carry them out
 impl Controllable for CompositeUnit {
-    fn new_order(&self, goal: Goal) {
-        todo!()
+    fn new_order(&mut self, goal: Goal) {
+        let goals = self.company_goals(&goal);
+        self.formation_positions = goals.iter().filter_map(Goal::target).collect();
+        for (company, g) in self.troops.iter_mut().zip(goals) {
+            company.new_order(g);
+        }
+
+        if let Some(dir) = goal.facing() {
+            self.direction = dir.normalise();
+        }
+        self.goals.clear();
+        self.goals.push_back(goal);
     }
 
-    fn add_order(&self, goal: Goal) {
-        todo!()
+    fn add_order(&mut self, goal: Goal) {
+        let goals = self.company_goals(&goal);
+        self.formation_positions = goals.iter().filter_map(Goal::target).collect();
+        for (company, g) in self.troops.iter_mut().zip(goals) {
+            company.add_order(g);
+        }
+
+        if self.goals.front().map_or(false, Goal::is_standing) {
+            self.goals.clear();
+        }
+        self.goals.push_back(goal);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::formations::Formation;
+    use crate::grid::SpatialGrid;
+    use crate::ops::Vec2f;
+    use crate::traits::Controllable;
+    use crate::units::{share_front, share_move, BasicUnit, Goal, Member, COMPANY_GAP};
+    use crate::world::{SimRng, TICK};
+    use rand::SeedableRng;
+
+    ///Steps the company until its queue is down to a standing goal, at most for secs
+    fn run(company: &mut BasicUnit, secs: f64, rng: &mut SimRng) {
+        let index = SpatialGrid::new(32.);
+        for _ in 0..(secs / TICK) as usize {
+            company.p_b(TICK, rng, &index);
+            if company.goals.len() == 1 && company.goals[0].is_standing() {
+                return;
+            }
+        }
+    }
+
+    #[test]
+    fn dragging_a_march_keeps_one_goal_and_the_ordered_facing() {
+        let mut rng = SimRng::seed_from_u64(0);
+        let mut company = BasicUnit::new(Vec2f::default(), 16, &mut rng);
+        let index = SpatialGrid::new(32.);
+        company.new_order(Goal::Move(Vec2f::from((0, 300)), Vec2f::from((1, 0))));
+
+        for k in 0..5 {
+            company.p_b(TICK, &mut rng, &index);
+            company.move_goal(0, Vec2f::from((k * 20, 300)));
+        }
+        assert_eq!(company.goals.len(), 1);
+        assert!(matches!(company.goals[0], Goal::Move(_, dir) if dir == Vec2f::from((1, 0))));
+
+        run(&mut company, 60., &mut rng);
+        assert!(matches!(company.goals[0], Goal::Hold));
+        assert!((company.direction - Vec2f::from((1, 0))).len() < 1e-3);
+    }
+
+    #[test]
+    fn a_queued_march_ends_on_the_ordered_facing() {
+        let mut rng = SimRng::seed_from_u64(0);
+        let mut company = BasicUnit::new(Vec2f::default(), 16, &mut rng);
+        let facing = Vec2f::from((-1, 0));
+        company.new_order(Goal::Move(Vec2f::from((200, 0)), Vec2f::from((1, 0))));
+        company.add_order(Goal::Move(Vec2f::from((200, 200)), facing));
+        company.add_order(Goal::Move(Vec2f::from((10, 205)), facing));
+        assert!(matches!(company.goals[0], Goal::Path(..)));
+
+        run(&mut company, 60., &mut rng);
+        assert!(matches!(company.goals[0], Goal::Hold));
+        assert_eq!(company.direction, facing);
+    }
+
+    #[test]
+    fn a_patrol_starts_over_when_its_queue_runs_out() {
+        let mut rng = SimRng::seed_from_u64(0);
+        let mut company = BasicUnit::new(Vec2f::default(), 16, &mut rng);
+        let a = Vec2f::from((0, 300));
+        let b = Vec2f::from((300, 300));
+        company.set_patrol(true);
+        company.new_order(Goal::Move(a, company.direction));
+        company.add_order(Goal::Column(b, None));
+
+        for _ in 0..3 {
+            company.next_goal();
+            assert!(matches!(company.goals[0], Goal::Column(p, None) if p == b));
+            company.next_goal();
+            assert!(matches!(company.goals[0], Goal::Move(p, _) if p == a));
+            assert_eq!(company.goals.len(), 2);
+        }
+
+        company.set_patrol(false);
+        company.next_goal();
+        company.next_goal();
+        assert!(matches!(company.goals[0], Goal::Hold));
+        assert_eq!(company.goals.len(), 1);
+
+        //as a scenario file leaves it, the queue is the route
+        company.patrol = true;
+        company.goals.clear();
+        company.goals.push_back(Goal::Column(a, None));
+        company.goals.push_back(Goal::Column(b, None));
+        company.next_goal();
+        company.next_goal();
+        assert!(matches!(company.goals[0], Goal::Column(p, None) if p == a));
+        assert_eq!(company.goals.len(), 2);
+    }
+
+    ///Five files in one rank across the x axis, facing down
+    fn line_of_five(rng: &mut SimRng) -> BasicUnit {
+        let mut company = BasicUnit::new(Vec2f::default(), 5, rng);
+        company.formation = Formation::phalanx(5);
+        company.direction = Vec2f::from((0, 1));
+        let spacing = company.formation.spacing();
+        company.formation_positions = (0..5).map(|k| Vec2f { x: (k as f64 - 2.) * spacing, y: 0. }).collect();
+        company
+    }
+
+    #[test]
+    fn a_wheel_pivots_on_the_near_flank_and_ends_on_the_facing() {
+        let mut rng = SimRng::seed_from_u64(0);
+        let quarter = std::f64::consts::FRAC_PI_4;
+
+        for (angle, flank) in [(quarter, 0), (-quarter, 4)] {
+            let mut company = line_of_five(&mut rng);
+            let facing = company.direction.rotate(angle);
+            let pivot_slot = company.formation_positions[flank];
+            let start = company.formation_positions.clone();
+            company.start_wheel(facing, angle);
+            assert!((company.wheel.unwrap().pivot - pivot_slot).len() < 1e-9);
+
+            company.reform = false;
+            for _ in 0..10_000 {
+                company.advance_wheel(TICK);
+                if company.wheel.is_none() {
+                    break;
+                }
+            }
+            assert!(company.wheel.is_none());
+            assert!(company.reform);
+            assert_eq!(company.direction, facing);
+            //turned as a rigid body: the pivot file stood still, the rest kept their distance to it
+            assert!((company.formation_positions[flank] - pivot_slot).len() < 1e-9);
+            for (p, s) in company.formation_positions.iter().zip(start.iter()) {
+                assert!(((*p - pivot_slot).len() - (*s - pivot_slot).len()).abs() < 1e-9);
+            }
+        }
+
+        let mut company = line_of_five(&mut rng);
+        let about = company.direction * -1.;
+        company.start_wheel(about, std::f64::consts::PI);
+        assert_eq!(company.wheel.unwrap().pivot, company.center);
+    }
+
+    ///The right hand company is listed first, so goals must come back in list order, not line order
+    fn two_companies() -> [Member; 2] {
+        [
+            Member { center: Vec2f::from((100, 0)), strength: 20, frontage: 40. },
+            Member { center: Vec2f::from((0, 0)), strength: 10, frontage: 60. },
+        ]
+    }
+
+    #[test]
+    fn a_front_is_shared_by_strength_without_crossing() {
+        let dir = Vec2f::from((0, -1));
+        let p2 = Vec2f { x: 300. + COMPANY_GAP, y: 0. };
+        let goals = share_front(Vec2f::default(), p2, dir, &two_companies());
+
+        match (&goals[0], &goals[1]) {
+            (Goal::Front(a1, a2, _), Goal::Front(b1, b2, _)) => {
+                assert!((*b1 - Vec2f::default()).len() < 1e-9);
+                assert!((*b2 - Vec2f::from((100, 0))).len() < 1e-9);
+                assert!((*a1 - Vec2f { x: 100. + COMPANY_GAP, y: 0. }).len() < 1e-9);
+                assert!((*a2 - p2).len() < 1e-9);
+            }
+            _ => panic!("expected two fronts, got {:?}", goals),
+        }
+    }
+
+    #[test]
+    fn a_move_places_companies_abreast_on_their_own_frontage() {
+        let dir = Vec2f::from((0, -1));
+        let goals = share_move(Vec2f::default(), dir, &two_companies());
+        let left = -(100. + COMPANY_GAP) / 2.;
+
+        match (&goals[0], &goals[1]) {
+            (Goal::Move(a, da), Goal::Move(b, db)) => {
+                assert!((*b - Vec2f { x: left + 30., y: 0. }).len() < 1e-9);
+                assert!((*a - Vec2f { x: left + 60. + COMPANY_GAP + 20., y: 0. }).len() < 1e-9);
+                assert_eq!((*da, *db), (dir, dir));
+            }
+            _ => panic!("expected two moves, got {:?}", goals),
+        }
     }
 }
@@ -1,21 +1,86 @@
 use std::any::Any;
-use crate::container::Container;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use crate::container::{Container, ContainerState};
 use crate::ops::Vec2f;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 use serde::{Deserialize, Serialize};
 use crate::drawable::Drawable;
-use crate::units::{BasicUnit, CompositeUnit, Unit};
+use crate::units::{company_of, front_facing, reserve_ids, BasicUnit, CompositeUnit, Goal, Unit};
 use crate::interaction::Interactable;
+use crate::grid::SpatialGrid;
+use crate::command::{CommandNode, Echelon, Hierarchy};
+use crate::troops::TroopCatalog;
+use crate::formations::{Formation, FORMATION_SPACING};
+use petgraph::stable_graph::NodeIndex;
 
-pub(crate) type WorldId = usize;
+pub type WorldId = usize;
 
 ///Used to select/deselect everything in the world
 pub const WORLD_ID: WorldId = 0;
 
+///How far from a unit or marker a click still picks it
+pub const CLICK_PRECISION: f64 = 12.;
+
+///Fixed simulation step, boid integration never sees the frame time
+pub const TICK: f64 = 1. / 60.;
+
+///Every random call in the simulation draws from the world's rng, so a seed reproduces a run
+pub type SimRng = StdRng;
+
+fn unseeded_rng() -> SimRng {
+    SimRng::seed_from_u64(0)
+}
+
+///Distances from the view centre to the edge of a company
+const HOT_DIST: f64 = 800.;
+const WARM_DIST: f64 = 2000.;
+///Companies this close to an engaged one run at least Warm
+const NEAR_ENGAGEMENT: f64 = 400.;
+
+const BOID_CELL: f64 = 32.;
+const UNIT_CELL: f64 = 256.;
+
+fn boid_grid() -> SpatialGrid<WorldId> {
+    SpatialGrid::new(BOID_CELL)
+}
+
+///entries are (index into groups, company or battalion id)
+fn unit_grid() -> SpatialGrid<(usize, WorldId)> {
+    SpatialGrid::new(UNIT_CELL)
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct World {
     pub groups: Vec<Unit>,
 
-    //pub nodes: Graph,
+    #[serde(default)]
+    pub seed: u64,
+    #[serde(skip, default = "unseeded_rng")]
+    pub rng: SimRng,
+
+    ///rebuilt at the start of every step
+    #[serde(skip, default = "boid_grid")]
+    pub boid_index: SpatialGrid<WorldId>,
+    #[serde(skip, default = "unit_grid")]
+    pub unit_index: SpatialGrid<(usize, WorldId)>,
+    ///largest select + interaction radius, how far around a unit the index has to be searched
+    #[serde(skip)]
+    max_reach: f64,
+
+    ///Centre of the camera, drives unit level of detail. None runs everything Hot
+    #[serde(skip)]
+    pub view: Option<Vec2f>,
+
+    ///troop types companies are equipped from by name
+    #[serde(skip, default = "TroopCatalog::builtin")]
+    pub catalog: TroopCatalog,
+
+    ///chain of command over the companies, any depth
+    #[serde(default)]
+    pub command: Hierarchy,
     //pub terrain: Array2D<i8>
 }
 
@@ -23,34 +88,158 @@ const BOID_NUM: usize = 20;
 
 impl Default for World {
     fn default() -> Self {
-        World {
-            groups: vec![]
-        }
+        World::with_seed(0)
     }
 }
 
 impl World {
-    pub fn single_company() -> Self {
+    pub fn with_seed(seed: u64) -> Self {
         World {
-            groups: vec![Unit::BasicUnit(BasicUnit::new(Vec2f::default(), BOID_NUM))],
+            groups: vec![],
+            seed,
+            rng: SimRng::seed_from_u64(seed),
+            boid_index: boid_grid(),
+            unit_index: unit_grid(),
+            max_reach: 0.,
+            view: None,
+            catalog: TroopCatalog::builtin(),
+            command: Hierarchy::default(),
         }
     }
 
-    pub fn single_battalion(num_companies: u8, units_per_company: u8) -> Self {
-        World {
-            groups: vec![Unit::CompositeUnit(CompositeUnit::new(Vec2f::default(), BOID_NUM))],
+    pub fn single_company(seed: u64) -> Self {
+        let mut world = World::with_seed(seed);
+        let company = BasicUnit::new(Vec2f::default(), BOID_NUM, &mut world.rng);
+        world.groups.push(Unit::BasicUnit(company));
+        world
+    }
+
+    pub fn single_battalion(seed: u64, num_companies: u8, units_per_company: u8) -> Self {
+        let mut world = World::with_seed(seed);
+        let battalion = CompositeUnit::new(
+            Vec2f::default(),
+            num_companies as usize,
+            units_per_company as usize,
+            &mut world.rng,
+        );
+        world.groups.push(Unit::CompositeUnit(battalion));
+        world
+    }
+
+    ///An army of legions of cohorts of centuries, each century a top level company of men,
+    ///drawn up in one line
+    pub fn army(seed: u64, legions: usize, cohorts: usize, centuries: usize, men: usize) -> Self {
+        let mut world = World::with_seed(seed);
+        let frontage = Formation::phalanx((men as f64).sqrt().ceil() as usize).frontage();
+        let step = frontage + FORMATION_SPACING * 2.;
+        let mut slot = 0;
+
+        let army = world.command.add(None, CommandNode::new("army".to_string(), Echelon::Army, None));
+        for l in 0..legions {
+            let name = format!("legion {}", l + 1);
+            let legion = world.command.add(Some(army), CommandNode::new(name, Echelon::Legion, None));
+
+            for c in 0..cohorts {
+                let name = format!("cohort {}", c + 1);
+                let cohort = world.command.add(Some(legion), CommandNode::new(name, Echelon::Cohort, None));
+
+                for k in 0..centuries {
+                    let pos = Vec2f { x: 0., y: slot as f64 * step };
+                    slot += 1;
+
+                    let company = BasicUnit::new(pos, men, &mut world.rng);
+                    let name = format!("century {}", k + 1);
+                    world.command.add(Some(cohort), CommandNode::new(name, Echelon::Century, Some(company.id)));
+                    world.groups.push(Unit::BasicUnit(company));
+                }
+            }
+        }
+        world
+    }
+
+    ///Restarts the random sequence, scenarios and bug reports carry the seed
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = SimRng::seed_from_u64(seed);
+    }
+
+    ///Every company takes its troop type's description from the catalog
+    pub fn equip_all(&mut self) -> Result<(), Box<dyn Error>> {
+        for company in self.groups.iter_mut().flat_map(|g| g.companies_mut()) {
+            match self.catalog.get(&company.troop_type) {
+                Some(desc) => company.equip(desc),
+                None => return Err(format!("company {}: unknown troop type {:?}", company.id, company.troop_type).into()),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn set_catalog(&mut self, catalog: TroopCatalog) -> Result<(), Box<dyn Error>> {
+        self.catalog = catalog;
+        self.equip_all()
+    }
+
+    pub fn rebuild_index(&mut self) {
+        self.boid_index.clear();
+        self.unit_index.clear();
+        self.max_reach = 0.;
+
+        for (i, group) in self.groups.iter().enumerate() {
+            if let Unit::CompositeUnit(b) = group {
+                self.unit_index.insert((i, b.id), b.center);
+                self.max_reach = self.max_reach.max(b.select_radius + b.interaction_radius);
+            }
+
+            for company in group.companies() {
+                self.unit_index.insert((i, company.id), company.center);
+                self.max_reach = self.max_reach.max(company.select_radius + company.interaction_radius);
+
+                if let Some(troops) = &company.troops {
+                    for (j, pos) in troops.pos.iter().enumerate() {
+                        self.boid_index.insert(company.id + j + 1, *pos);
+                    }
+                }
+            }
         }
     }
 
     //maybe results should be in a hashset?
     pub fn get_ids_at(&self, pos: Vec2f) -> Vec<WorldId> {
+        //battalions come before the companies in them, like companies before their boids
+        let mut battalions = vec![];
         let mut sel = vec![];
 
-        for group in self.groups.iter() {
-            if group.is_in_bounds(pos) {
-                sel.push(group.id);
-                if let Some(b) = group.get_boid_at(pos) {
-                    sel.push(b);
+        for ((i, id), center) in self.unit_index.query_radius(pos, self.max_reach) {
+            match &self.groups[i] {
+                Unit::CompositeUnit(b) if b.id == id => {
+                    if (pos - center).len() < b.select_radius {
+                        battalions.push(id);
+                    }
+                }
+                group => {
+                    let hit = group.companies().iter().find(|c| c.id == id);
+                    if hit.map_or(false, |c| (pos - center).len() < c.select_radius) {
+                        sel.push(id);
+                    }
+                }
+            }
+        }
+        battalions.append(&mut sel);
+        let mut sel = battalions;
+
+        //closest boid under the cursor goes after its company
+        let boid = self
+            .boid_index
+            .query_radius(pos, CLICK_PRECISION)
+            .min_by(|a, b| (a.1 - pos).len().total_cmp(&(b.1 - pos).len()));
+
+        if let Some((id, _)) = boid {
+            let company = company_of(id);
+            match sel.iter().position(|c| *c == company) {
+                Some(i) => sel.insert(i + 1, id),
+                None => {
+                    sel.push(company);
+                    sel.push(id);
                 }
             }
         }
@@ -60,20 +249,357 @@ impl World {
 
     //maybe results should be in a hashset?
     pub(crate) fn get_ids_in_rect(&self, p0: Vec2f, p1: Vec2f) -> Vec<WorldId> {
-        let mut sel = vec![];
+        self.unit_index.query_rect(p0, p1).map(|((_, id), _)| id).collect()
+    }
 
-        for group in self.groups.iter() {}
+    pub fn find_company(&self, id: WorldId) -> Option<&BasicUnit> {
+        self.groups.iter().flat_map(|g| g.companies()).find(|c| c.id == id)
+    }
 
-        sel
+    pub fn find_company_mut(&mut self, id: WorldId) -> Option<&mut BasicUnit> {
+        self.groups.iter_mut().flat_map(|g| g.companies_mut()).find(|c| c.id == id)
     }
 
-    pub(crate) fn process_interactions(&self) {
-        for mut group in self.groups {
-            for mut other in self.groups {
-                group.manage_interaction(&mut other);
+    ///(company id, goal index, marker position) of the queued goal marker under pos,
+    ///only companies drawn as selected show theirs
+    pub fn goal_at(&self, pos: Vec2f) -> Option<(WorldId, usize, Vec2f)> {
+        self.groups
+            .iter()
+            .flat_map(|g| g.companies())
+            .filter(|c| c.selected)
+            .find_map(|c| c.goal_at(pos).map(|(i, m)| (c.id, i, m)))
+    }
+
+    ///(company id, goal) for every company a front from p1 to p2 would be ordered to the selection,
+    ///shared out the way App::update hands it down
+    pub fn front_goals(
+        &self,
+        p1: Vec2f,
+        p2: Vec2f,
+        selected: &HashSet<WorldId>,
+        command: Option<NodeIndex>,
+    ) -> Vec<(WorldId, Goal)> {
+        let mut goals = vec![];
+        if let Some(node) = command {
+            let center = self.command.member(node, self).center;
+            goals = self.command.distribute(node, Goal::Front(p1, p2, front_facing(p1, p2, center)), self);
+        }
+        let commanded: Vec<WorldId> = goals.iter().map(|(id, _)| *id).collect();
+
+        for group in self.groups.iter() {
+            if let Unit::CompositeUnit(battalion) = group {
+                if selected.contains(&battalion.id) {
+                    let goal = Goal::Front(p1, p2, front_facing(p1, p2, battalion.center));
+                    let ids = battalion.troops.iter().map(|c| c.id);
+                    goals.extend(ids.zip(battalion.company_goals(&goal)));
+                    continue;
+                }
+            }
+
+            for company in group.companies() {
+                if selected.contains(&company.id) && !commanded.contains(&company.id) {
+                    goals.push((company.id, Goal::Front(p1, p2, front_facing(p1, p2, company.center))));
+                }
             }
         }
+        goals
+    }
+
+    ///Takes the top level companies with the given ids into a new battalion, returns its id
+    pub fn assemble(&mut self, ids: &[WorldId]) -> Option<WorldId> {
+        let (taken, kept): (Vec<Unit>, Vec<Unit>) = self.groups
+            .drain(..)
+            .partition(|g| matches!(g, Unit::BasicUnit(c) if ids.contains(&c.id)));
+        self.groups = kept;
+
+        let companies: Vec<BasicUnit> = taken
+            .into_iter()
+            .filter_map(|g| match g {
+                Unit::BasicUnit(c) => Some(c),
+                Unit::CompositeUnit(_) => None,
+            })
+            .collect();
+
+        if companies.is_empty() {
+            return None;
+        }
+
+        let mut battalion = CompositeUnit::assemble(companies);
+        battalion.selected = battalion.troops.iter().any(|c| c.selected);
+        let id = battalion.id;
+        self.groups.push(Unit::CompositeUnit(battalion));
+        self.rebuild_index();
+        Some(id)
+    }
+
+    ///Moves a top level company into a battalion
+    pub fn attach(&mut self, company: WorldId, battalion: WorldId) -> bool {
+        let has_battalion = self.groups.iter()
+            .any(|g| matches!(g, Unit::CompositeUnit(b) if b.id == battalion));
+        let pos = self.groups.iter()
+            .position(|g| matches!(g, Unit::BasicUnit(c) if c.id == company));
+
+        let company = match (has_battalion, pos) {
+            (true, Some(i)) => match self.groups.remove(i) {
+                Unit::BasicUnit(c) => c,
+                Unit::CompositeUnit(_) => unreachable!(),
+            },
+            _ => return false,
+        };
+
+        for group in self.groups.iter_mut() {
+            if let Unit::CompositeUnit(b) = group {
+                if b.id == battalion {
+                    b.attach_company(company);
+                    break;
+                }
+            }
+        }
+        self.rebuild_index();
+        true
+    }
+
+    ///Moves a company out of its battalion to the top level, a battalion left empty is disbanded
+    pub fn detach(&mut self, company: WorldId) -> bool {
+        let mut detached = None;
+        for group in self.groups.iter_mut() {
+            if let Unit::CompositeUnit(b) = group {
+                if let Some(c) = b.detach_company(company) {
+                    detached = Some(c);
+                    break;
+                }
+            }
+        }
+
+        match detached {
+            Some(c) => {
+                self.groups.retain(|g| !matches!(g, Unit::CompositeUnit(b) if b.troops.is_empty()));
+                self.groups.push(Unit::BasicUnit(c));
+                self.rebuild_index();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn process_interactions(&mut self) {
+        for i in 0..self.groups.len() {
+            let reach = self.max_reach + self.groups[i].companies().iter()
+                .map(|c| c.select_radius + c.interaction_radius)
+                .fold(0., f64::max);
+
+            let mut others: Vec<usize> = vec![];
+            for company in self.groups[i].companies() {
+                others.extend(self.unit_index.query_radius(company.center, reach)
+                    .map(|((j, _), _)| j)
+                    .filter(|j| *j > i));
+            }
+            others.sort_unstable();
+            others.dedup();
+
+            for j in others {
+                let (head, tail) = self.groups.split_at_mut(j);
+                head[i].interact(&mut tail[0], &self.boid_index);
+            }
+        }
+    }
+
+    ///Hot when selected, engaged or close to the view, Warm near engagements or
+    ///further out, Cold otherwise
+    fn update_lod(&mut self) {
+        let view = match self.view {
+            Some(view) => view,
+            None => {
+                for company in self.groups.iter_mut().flat_map(|g| g.companies_mut()) {
+                    company.to_hot();
+                }
+                return;
+            }
+        };
+
+        let engagements: Vec<Vec2f> = self.groups.iter()
+            .flat_map(|g| g.companies())
+            .filter(|c| c.engaged)
+            .map(|c| c.center)
+            .collect();
+
+        for company in self.groups.iter_mut().flat_map(|g| g.companies_mut()) {
+            let dist = (company.center - view).len() - company.select_radius;
+            let near_engagement = engagements.iter().any(|e| (*e - company.center).len() < NEAR_ENGAGEMENT);
+
+            let tier = if company.selected || company.engaged || dist < HOT_DIST {
+                ContainerState::Hot
+            } else if near_engagement || dist < WARM_DIST {
+                ContainerState::Warm
+            } else {
+                ContainerState::Cold
+            };
+
+            if tier != company.cur_state() {
+                match tier {
+                    ContainerState::Cold => company.to_cold(),
+                    ContainerState::Warm => company.to_warm(),
+                    ContainerState::Hot => company.to_hot(),
+                }
+            }
+        }
+    }
+
+    ///Advances the simulation by dt seconds, no window or graphics context needed
+    pub fn step(&mut self, dt: f64) {
+        self.rebuild_index();
+
+        for company in self.groups.iter_mut().flat_map(|g| g.companies_mut()) {
+            company.engaged = false;
+            company.in_melee = false;
+        }
+        self.process_interactions();
+        self.update_lod();
+
+        for group in self.groups.iter_mut() {
+            group.process_boids(dt, &mut self.rng, &self.boid_index);
+        }
+
+        let status: HashMap<WorldId, (usize, f32)> = self.groups.iter()
+            .flat_map(|g| g.companies())
+            .map(|c| (c.id, (c.num_alive(), c.morale)))
+            .collect();
+        self.command.aggregate(&status);
+    }
+
+    ///Companies are equipped from catalog, which has to know every troop type the scenario names
+    pub fn load(path: &str, catalog: TroopCatalog) -> Result<World, Box<dyn Error>> {
+        let text = fs::read_to_string(path)?;
+        let mut world: World = ron::from_str(&text)?;
+        world.set_seed(world.seed);
+        world.set_catalog(catalog)?;
+        for group in world.groups.iter() {
+            if let Unit::CompositeUnit(b) = group {
+                reserve_ids(b.id);
+            }
+            for company in group.companies() {
+                reserve_ids(company.id);
+            }
+        }
+        world.rebuild_index();
+        Ok(world)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+        fs::write(path, text)?;
+        Ok(())
     }
 
     //pub fn assign
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::container::{Container, ContainerState};
+    use crate::ops::Vec2f;
+    use crate::troops::TroopCatalog;
+    use crate::units::{BasicUnit, CompositeUnit, Unit};
+    use crate::world::{World, WorldId, TICK};
+
+    #[test]
+    fn a_line_of_friends_far_from_view_goes_cold() {
+        let mut world = World::army(1, 1, 2, 6, 64);
+        world.view = Some(Vec2f { x: 100_000., y: 0. });
+        world.step(TICK);
+
+        for company in world.groups.iter().flat_map(|g| g.companies()) {
+            assert!(!company.engaged);
+            assert_eq!(company.cur_state(), ContainerState::Cold);
+        }
+    }
+
+    #[test]
+    fn load_equips_from_the_given_catalog() {
+        let catalog = TroopCatalog::parse(r#"[(name: "hoplites", base_spd: 80.)]"#).unwrap();
+        let mut world = World::single_company(0);
+        world.set_catalog(catalog.clone()).unwrap();
+        for company in world.groups.iter_mut().flat_map(|g| g.companies_mut()) {
+            company.equip(catalog.get("hoplites").unwrap());
+        }
+
+        let path = std::env::temp_dir().join("boids_load_catalog.ron");
+        let path = path.to_str().unwrap();
+        world.save(path).unwrap();
+
+        assert!(World::load(path, TroopCatalog::builtin()).is_err());
+        let world = World::load(path, catalog).unwrap();
+        let desc = &world.groups[0].companies()[0].troop_desc;
+        assert_eq!(desc.name, "hoplites");
+        assert_eq!(desc.base_spd, 80.);
+    }
+
+    #[test]
+    fn clicking_a_battalion_selects_it_first() {
+        let mut world = World::single_battalion(0, 2, 16);
+        world.step(TICK);
+        world.rebuild_index();
+        let (id, center) = match &world.groups[0] {
+            Unit::CompositeUnit(b) => (b.id, b.center),
+            Unit::BasicUnit(_) => unreachable!(),
+        };
+
+        let ids = world.get_ids_at(center);
+        assert_eq!(ids.first(), Some(&id));
+        assert!(world.get_ids_at(center + Vec2f { x: 100_000., y: 0. }).is_empty());
+    }
+
+    ///Companies of 4 at x along the x axis, each 10 across
+    fn companies_at(xs: &[f64]) -> (World, Vec<WorldId>) {
+        let mut world = World::with_seed(0);
+        let mut ids = vec![];
+        for &x in xs {
+            let mut company = BasicUnit::new(Vec2f { x, y: 0. }, 4, &mut world.rng);
+            company.select_radius = 10.;
+            ids.push(company.id);
+            world.groups.push(Unit::BasicUnit(company));
+        }
+        (world, ids)
+    }
+
+    fn battalion(world: &World) -> Option<&CompositeUnit> {
+        world.groups.iter().find_map(|g| match g {
+            Unit::CompositeUnit(b) => Some(b),
+            Unit::BasicUnit(_) => None,
+        })
+    }
+
+    #[test]
+    fn battalions_keep_their_centre_and_radius_through_attach_and_detach() {
+        let (mut world, ids) = companies_at(&[0., 200., 400.]);
+
+        let id = world.assemble(&ids[..2]).unwrap();
+        assert_eq!(world.groups.len(), 2);
+        let b = battalion(&world).unwrap();
+        assert_eq!(b.id, id);
+        assert!(!ids.contains(&id));
+        assert_eq!(b.center, Vec2f { x: 100., y: 0. });
+        assert_eq!(b.select_radius, 110.);
+
+        assert!(!world.attach(ids[2], id + 1));
+        assert!(world.attach(ids[2], id));
+        assert_eq!(world.groups.len(), 1);
+        let b = battalion(&world).unwrap();
+        assert_eq!(b.troops.len(), 3);
+        assert_eq!(b.center, Vec2f { x: 200., y: 0. });
+        assert_eq!(b.select_radius, 210.);
+
+        assert!(world.detach(ids[0]));
+        let b = battalion(&world).unwrap();
+        assert_eq!(b.id, id);
+        assert_eq!(b.center, Vec2f { x: 300., y: 0. });
+        assert_eq!(b.select_radius, 110.);
+        assert!(world.groups.iter().any(|g| matches!(g, Unit::BasicUnit(c) if c.id == ids[0])));
+
+        assert!(world.detach(ids[1]));
+        assert!(world.detach(ids[2]));
+        assert!(!world.detach(ids[2]));
+        assert!(battalion(&world).is_none());
+        assert_eq!(world.groups.len(), 3);
+    }
+}